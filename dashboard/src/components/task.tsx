@@ -55,12 +55,14 @@ const Task = (props: TaskProps) => {
         "WRITE": "red",
         "READ": "green",
         "CLEAN": "red",
+        "SEMAPHORE": "orange",
     }
 
     const BADGE_LOCK_CONTENT = {
         "WRITE": "W",
         "READ": "R",
         "CLEAN": "C",
+        "SEMAPHORE": "S",
     }
 
     return (
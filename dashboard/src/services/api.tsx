@@ -5,7 +5,7 @@ type ITask = {
     id: string,
     display_name: string,
     locks: {
-        type: "WRITE" | "READ" | "CLEAN"
+        type: "WRITE" | "READ" | "CLEAN" | "SEMAPHORE"
         name: string,
         poisoned: string,
     }[]
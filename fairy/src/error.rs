@@ -47,9 +47,12 @@ pub enum Error {
     #[snafu(display("missing log pipe: {which}"))]
     MissingPipe { which: &'static str },
 
-    #[snafu(display("the runner stopped sending heartbeats, timed out and got killed"))]
+    #[snafu(display("the heartbeat was rejected, the task timed out and the runner got killed"))]
     Timeout,
 
+    #[snafu(display("the cancellation of the task was requested and the runner got killed"))]
+    Cancelled,
+
     #[snafu(display("process was killed but process isn't exiting"))]
     NixZombie,
 }
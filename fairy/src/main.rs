@@ -10,13 +10,13 @@ use std::pin::pin;
 use std::process::{Stdio, exit};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::process::Command;
+use tokio::process::{Child, Command};
 use tokio::select;
 use tokio::time::interval;
 use tokio_util::codec::{FramedRead, LinesCodec};
 use uuid::Uuid;
 use vickylib::database::entities::Task;
-use vickylib::database::entities::task::{EXPECTED_HEARTBEAT_INTERVAL_SEC, TaskResult};
+use vickylib::database::entities::task::{EXPECTED_HEARTBEAT_INTERVAL_SEC, Heartbeat, TaskResult};
 use which::which;
 
 mod error;
@@ -164,6 +164,7 @@ async fn try_run_task(cfg: Arc<AppConfig>, task: &Task) -> Result<()> {
         .env("VICKY_URL", &cfg.vicky_external_url)
         .env("VICKY_TOKEN", &cfg.machine_token)
         .kill_on_drop(true)
+        .process_group(0)
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -196,14 +197,20 @@ async fn try_run_task(cfg: Arc<AppConfig>, task: &Task) -> Result<()> {
     let mut sink = pin!(lines);
     let mut tick = interval(Duration::from_secs(EXPECTED_HEARTBEAT_INTERVAL_SEC as u64));
 
-    let mut force_exit = false;
+    let mut kill_reason = None;
     loop {
         select! {
             r = &mut sink => break r?,
-            _ = tick.tick() => {
-                if let Err(e) = heartbeat(&cfg, task).await {
+            _ = tick.tick() => match heartbeat(&cfg, task).await {
+                Ok(Heartbeat { cancel_requested: true }) => {
+                    info!("Cancellation of the task was requested, killing it.");
+                    kill_reason = Some(Error::Cancelled);
+                    break;
+                }
+                Ok(_) => {}
+                Err(e) => {
                     warn!("Received failure response when sending heartbeat. (Did I time out?): {e}");
-                    force_exit = true;
+                    kill_reason = Some(Error::Timeout);
                     break;
                 }
             },
@@ -212,9 +219,10 @@ async fn try_run_task(cfg: Arc<AppConfig>, task: &Task) -> Result<()> {
 
     tick.reset_immediately(); // use as timeout
 
-    if force_exit {
+    if let Some(kill_reason) = kill_reason {
+        kill_process_group(&child);
         select! {
-            _ = child.kill() => return Err(Error::Timeout),
+            _ = child.kill() => return Err(kill_reason),
             _ = tick.tick() => return Err(Error::NixZombie),
         }
     }
@@ -234,9 +242,24 @@ async fn try_run_task(cfg: Arc<AppConfig>, task: &Task) -> Result<()> {
     Ok(())
 }
 
-async fn heartbeat(cfg: &AppConfig, task: &Task) -> Result<()> {
+/// Kills everything nix spawned for the task, as killing nix alone would leave its children running.
+fn kill_process_group(child: &Child) {
+    let Some(pid) = child.id() else {
+        return; // already exited
+    };
+
+    // SAFETY: only sends a signal to the process group that was created for this child
+    if unsafe { libc::killpg(pid as libc::pid_t, libc::SIGKILL) } != 0 {
+        warn!(
+            "Could not kill process group {pid}: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+async fn heartbeat(cfg: &AppConfig, task: &Task) -> Result<Heartbeat> {
     debug!("Sending heartbeat");
-    api::<(), ()>(
+    api::<(), Heartbeat>(
         cfg,
         Method::POST,
         &format!("api/v1/tasks/{}/heartbeat", task.id),
@@ -248,6 +271,10 @@ async fn heartbeat(cfg: &AppConfig, task: &Task) -> Result<()> {
 async fn run_task(cfg: Arc<AppConfig>, task: Task) {
     #[cfg(not(feature = "nixless-test-mode"))]
     let result = match try_run_task(cfg.clone(), &task).await {
+        Err(Error::Cancelled) => {
+            info!("task cancelled: {} {}", task.id, task.display_name);
+            TaskResult::Cancel
+        }
         Err(e) => {
             info!("task failed: {} {} ({:?})", task.id, task.display_name, e);
             TaskResult::Error
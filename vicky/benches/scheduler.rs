@@ -0,0 +1,109 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use diesel::{Connection, PgConnection};
+use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
+use std::hint::black_box;
+use vickylib::database::entities::Task;
+use vickylib::database::entities::task::db_impl::TaskDatabase;
+use vickylib::database::entities::task::{TaskResult, TaskStatus};
+use vickylib::vicky::scheduler::Scheduler;
+
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+
+const PENDING_TASKS: usize = 200;
+const HISTORY_SIZES: [usize; 3] = [1_000, 10_000, 50_000];
+
+fn build_task(index: usize, status: TaskStatus) -> Task {
+    Task::builder()
+        .display_name(format!("Task {index}"))
+        .status(status)
+        .write_lock(format!("host/{}", index % 50))
+        .read_lock("shared")
+        .build()
+        .unwrap_or_else(|_| panic!("benchmark task {index} has conflicting locks"))
+}
+
+fn pending_tasks() -> Vec<Task> {
+    (0..PENDING_TASKS)
+        .map(|i| {
+            let status = if i < 10 {
+                TaskStatus::Running
+            } else {
+                TaskStatus::New
+            };
+            build_task(i, status)
+        })
+        .collect()
+}
+
+fn with_history(pending: &[Task], history: usize) -> Vec<Task> {
+    (0..history)
+        .map(|i| build_task(i, TaskStatus::Finished(TaskResult::Success)))
+        .chain(pending.iter().cloned())
+        .collect()
+}
+
+fn next_task(tasks: &Vec<Task>) -> Option<Task> {
+    Scheduler::new(tasks, &[], &[])
+        .expect("benchmark tasks must not conflict")
+        .get_next_task()
+}
+
+fn bench_next_task(c: &mut Criterion) {
+    let pending = pending_tasks();
+    let mut group = c.benchmark_group("get_next_task");
+
+    group.bench_function("pending_only", |b| {
+        b.iter(|| next_task(black_box(&pending)))
+    });
+
+    for history in HISTORY_SIZES {
+        let all = with_history(&pending, history);
+        group.bench_with_input(BenchmarkId::new("with_history", history), &all, |b, all| {
+            b.iter(|| next_task(black_box(all)))
+        });
+    }
+
+    group.finish();
+}
+
+/// Loads the tasks for scheduling from a database seeded with a growing task history. Needs a
+/// disposable postgres database, passed via `VICKY_TEST_DATABASE_URL`.
+fn bench_load_tasks(c: &mut Criterion) {
+    let Ok(url) = std::env::var("VICKY_TEST_DATABASE_URL") else {
+        eprintln!("skipping load_tasks: VICKY_TEST_DATABASE_URL is not set");
+        return;
+    };
+    let mut conn = PgConnection::establish(&url).expect("benchmark database should be reachable");
+    conn.run_pending_migrations(MIGRATIONS)
+        .expect("migrations should apply");
+    // the seeded tasks are rolled back when the connection is dropped
+    conn.begin_test_transaction()
+        .expect("seeding transaction should start");
+
+    for task in pending_tasks() {
+        conn.put_task(task).expect("pending task should be seeded");
+    }
+
+    let mut group = c.benchmark_group("load_tasks");
+    let mut seeded = 0;
+
+    for history in HISTORY_SIZES {
+        for i in seeded..history {
+            conn.put_task(build_task(i, TaskStatus::Finished(TaskResult::Success)))
+                .expect("finished task should be seeded");
+        }
+        seeded = history;
+
+        group.bench_function(BenchmarkId::new("get_all_tasks", history), |b| {
+            b.iter(|| conn.get_all_tasks().expect("tasks should load"))
+        });
+        group.bench_function(BenchmarkId::new("get_pending_tasks", history), |b| {
+            b.iter(|| conn.get_pending_tasks().expect("tasks should load"))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_next_task, bench_load_tasks);
+criterion_main!(benches);
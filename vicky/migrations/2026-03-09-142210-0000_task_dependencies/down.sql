@@ -0,0 +1,22 @@
+DROP TABLE task_dependencies;
+
+-- can't drop enum values from an enum.
+CREATE TYPE "TaskStatus_Type_New" AS ENUM (
+    'NEW',
+    'NEEDS_USER_VALIDATION',
+    'RUNNING',
+    'FINISHED::SUCCESS',
+    'FINISHED::ERROR',
+    'FINISHED::TIMEOUT',
+    'FINISHED::CANCEL'
+);
+
+UPDATE tasks SET status = 'FINISHED::CANCEL' WHERE status = 'FINISHED::DEPENDENCY_FAILED';
+
+ALTER TABLE tasks
+    ALTER COLUMN status TYPE "TaskStatus_Type_New"
+        USING (status::text::"TaskStatus_Type_New");
+
+DROP TYPE "TaskStatus_Type";
+
+ALTER TYPE "TaskStatus_Type_New" RENAME TO "TaskStatus_Type";
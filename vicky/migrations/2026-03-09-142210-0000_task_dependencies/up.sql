@@ -0,0 +1,14 @@
+CREATE TABLE task_dependencies
+(
+    task_id    uuid NOT NULL,
+    depends_on uuid NOT NULL,
+    PRIMARY KEY (task_id, depends_on),
+    CONSTRAINT fk_task
+        FOREIGN KEY (task_id)
+            REFERENCES tasks (id),
+    CONSTRAINT fk_depends_on
+        FOREIGN KEY (depends_on)
+            REFERENCES tasks (id)
+);
+
+ALTER TYPE "TaskStatus_Type" ADD VALUE 'FINISHED::DEPENDENCY_FAILED';
@@ -0,0 +1,19 @@
+ALTER TABLE locks
+    DROP "capacity";
+
+-- can't drop enum values from an enum.
+CREATE TYPE "LockKind_Type_New" AS ENUM (
+    'READ',
+    'WRITE',
+    'CLEAN'
+);
+
+UPDATE locks SET type = 'WRITE' WHERE type = 'SEMAPHORE';
+
+ALTER TABLE locks
+    ALTER COLUMN type TYPE "LockKind_Type_New"
+        USING (type::text::"LockKind_Type_New");
+
+DROP TYPE "LockKind_Type";
+
+ALTER TYPE "LockKind_Type_New" RENAME TO "LockKind_Type";
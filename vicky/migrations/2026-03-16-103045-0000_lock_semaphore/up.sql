@@ -0,0 +1,4 @@
+ALTER TYPE "LockKind_Type" ADD VALUE 'SEMAPHORE';
+
+ALTER TABLE locks
+    ADD COLUMN "capacity" INTEGER;
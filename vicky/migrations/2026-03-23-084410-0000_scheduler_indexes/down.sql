@@ -0,0 +1,4 @@
+DROP INDEX task_dependencies_depends_on_idx;
+DROP INDEX locks_poisoned_by_task_idx;
+DROP INDEX locks_task_id_idx;
+DROP INDEX tasks_status_idx;
@@ -0,0 +1,4 @@
+CREATE INDEX tasks_status_idx ON tasks (status);
+CREATE INDEX locks_task_id_idx ON locks (task_id);
+CREATE INDEX locks_poisoned_by_task_idx ON locks (poisoned_by_task) WHERE poisoned_by_task IS NOT NULL;
+CREATE INDEX task_dependencies_depends_on_idx ON task_dependencies (depends_on);
@@ -0,0 +1,2 @@
+DROP TABLE schedule_locks;
+DROP TABLE schedules;
@@ -0,0 +1,32 @@
+CREATE TABLE schedules
+(
+    id             uuid PRIMARY KEY,
+    display_name   VARCHAR     NOT NULL,
+    cron           VARCHAR     NOT NULL,
+    flake_ref_uri  VARCHAR     NOT NULL,
+    flake_ref_args text[]      NOT NULL,
+    features       text[]      NOT NULL,
+    "group"        VARCHAR,
+    created_at     timestamptz NOT NULL DEFAULT now(),
+    next_run_at    timestamptz,
+    last_task_id   uuid,
+    CONSTRAINT fk_last_task
+        FOREIGN KEY (last_task_id)
+            REFERENCES tasks (id)
+);
+
+CREATE TABLE schedule_locks
+(
+    id          uuid DEFAULT uuid_generate_v4() PRIMARY KEY,
+    schedule_id uuid            NOT NULL,
+    name        VARCHAR         NOT NULL,
+    type        "LockKind_Type" NOT NULL,
+    capacity    INTEGER,
+    CONSTRAINT fk_schedule
+        FOREIGN KEY (schedule_id)
+            REFERENCES schedules (id)
+            ON DELETE CASCADE
+);
+
+CREATE INDEX schedules_next_run_at_idx ON schedules (next_run_at);
+CREATE INDEX schedule_locks_schedule_id_idx ON schedule_locks (schedule_id);
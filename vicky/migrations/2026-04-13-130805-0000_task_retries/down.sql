@@ -0,0 +1,6 @@
+ALTER TABLE tasks
+    DROP "retry_on",
+    DROP "retry_backoff_sec",
+    DROP "retry_max_attempts",
+    DROP "retry_of",
+    DROP "attempt";
@@ -0,0 +1,8 @@
+ALTER TABLE tasks
+    ADD COLUMN "attempt"            INTEGER NOT NULL DEFAULT 1,
+    ADD COLUMN "retry_of"           uuid REFERENCES tasks (id),
+    ADD COLUMN "retry_max_attempts" INTEGER,
+    ADD COLUMN "retry_backoff_sec"  INTEGER,
+    ADD COLUMN "retry_on"           text[];
+
+CREATE INDEX tasks_retry_of_idx ON tasks (retry_of);
@@ -0,0 +1,2 @@
+ALTER TABLE tasks
+    ADD COLUMN "max_runtime" INTEGER;
@@ -0,0 +1,2 @@
+ALTER TABLE tasks
+    DROP "cancel_requested_at";
@@ -0,0 +1,2 @@
+ALTER TABLE tasks
+    ADD COLUMN "cancel_requested_at" TIMESTAMPTZ;
@@ -0,0 +1,3 @@
+ALTER TABLE tasks
+    DROP "rerun_of",
+    DROP "needs_confirmation";
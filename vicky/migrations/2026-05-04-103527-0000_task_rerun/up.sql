@@ -0,0 +1,7 @@
+ALTER TABLE tasks
+    ADD COLUMN "needs_confirmation" BOOLEAN NOT NULL DEFAULT false,
+    ADD COLUMN "rerun_of"           uuid REFERENCES tasks (id);
+
+-- Confirmations of existing tasks weren't recorded, so re-runs of them have to be confirmed again.
+UPDATE tasks
+SET needs_confirmation = true;
@@ -0,0 +1,17 @@
+CREATE TABLE task_edits
+(
+    id        uuid DEFAULT uuid_generate_v4() PRIMARY KEY,
+    task_id   uuid        NOT NULL,
+    edited_at timestamptz NOT NULL DEFAULT now(),
+    edited_by uuid,
+    changes   jsonb       NOT NULL,
+    CONSTRAINT fk_task
+        FOREIGN KEY (task_id)
+            REFERENCES tasks (id)
+            ON DELETE CASCADE,
+    CONSTRAINT fk_user
+        FOREIGN KEY (edited_by)
+            REFERENCES users (id)
+);
+
+CREATE INDEX task_edits_task_id_idx ON task_edits (task_id);
@@ -0,0 +1,13 @@
+CREATE TABLE task_labels
+(
+    task_id uuid    NOT NULL,
+    key     VARCHAR NOT NULL,
+    value   VARCHAR NOT NULL,
+    PRIMARY KEY (task_id, key),
+    CONSTRAINT fk_task
+        FOREIGN KEY (task_id)
+            REFERENCES tasks (id)
+            ON DELETE CASCADE
+);
+
+CREATE INDEX task_labels_key_value_idx ON task_labels (key, value);
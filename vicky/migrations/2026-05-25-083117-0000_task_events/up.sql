@@ -0,0 +1,22 @@
+CREATE TABLE task_events
+(
+    id            uuid DEFAULT uuid_generate_v4() PRIMARY KEY,
+    task_id       uuid              NOT NULL,
+    created_at    timestamptz       NOT NULL DEFAULT now(),
+    from_status   "TaskStatus_Type" NOT NULL,
+    to_status     "TaskStatus_Type" NOT NULL,
+    actor_user    uuid,
+    actor_machine VARCHAR,
+    reason        VARCHAR,
+    CONSTRAINT fk_task
+        FOREIGN KEY (task_id)
+            REFERENCES tasks (id)
+            ON DELETE CASCADE,
+    CONSTRAINT fk_user
+        FOREIGN KEY (actor_user)
+            REFERENCES users (id),
+    CONSTRAINT single_actor
+        CHECK (actor_user IS NULL OR actor_machine IS NULL)
+);
+
+CREATE INDEX task_events_task_id_idx ON task_events (task_id);
@@ -0,0 +1,5 @@
+DROP TABLE task_approvals;
+
+ALTER TABLE tasks
+    DROP "created_by",
+    DROP "required_approvals";
@@ -0,0 +1,18 @@
+ALTER TABLE tasks
+    ADD COLUMN "required_approvals" INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN "created_by"         uuid REFERENCES users (id);
+
+CREATE TABLE task_approvals
+(
+    task_id     uuid        NOT NULL,
+    user_id     uuid        NOT NULL,
+    approved_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (task_id, user_id),
+    CONSTRAINT fk_task
+        FOREIGN KEY (task_id)
+            REFERENCES tasks (id)
+            ON DELETE CASCADE,
+    CONSTRAINT fk_user
+        FOREIGN KEY (user_id)
+            REFERENCES users (id)
+);
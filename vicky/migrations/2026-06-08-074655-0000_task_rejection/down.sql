@@ -0,0 +1,4 @@
+ALTER TABLE tasks
+    DROP "rejected_by",
+    DROP "rejected_at",
+    DROP "rejection_comment";
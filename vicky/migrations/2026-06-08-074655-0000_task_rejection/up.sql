@@ -0,0 +1,4 @@
+ALTER TABLE tasks
+    ADD COLUMN "rejection_comment" VARCHAR,
+    ADD COLUMN "rejected_at"       timestamptz,
+    ADD COLUMN "rejected_by"       uuid REFERENCES users (id);
@@ -0,0 +1,2 @@
+ALTER TABLE tasks
+    ADD COLUMN "idempotency_key" VARCHAR UNIQUE;
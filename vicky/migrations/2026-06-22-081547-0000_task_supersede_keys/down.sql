@@ -0,0 +1,33 @@
+DROP INDEX tasks_supersede_key_idx;
+
+ALTER TABLE tasks
+    DROP "supersede_key";
+
+-- can't drop enum values from an enum.
+CREATE TYPE "TaskStatus_Type_New" AS ENUM (
+    'NEW',
+    'NEEDS_USER_VALIDATION',
+    'RUNNING',
+    'FINISHED::SUCCESS',
+    'FINISHED::ERROR',
+    'FINISHED::TIMEOUT',
+    'FINISHED::CANCEL',
+    'FINISHED::DEPENDENCY_FAILED'
+);
+
+UPDATE tasks SET status = 'FINISHED::CANCEL' WHERE status = 'FINISHED::SUPERSEDED';
+UPDATE task_events SET to_status = 'FINISHED::CANCEL' WHERE to_status = 'FINISHED::SUPERSEDED';
+
+ALTER TABLE tasks
+    ALTER COLUMN status TYPE "TaskStatus_Type_New"
+        USING (status::text::"TaskStatus_Type_New");
+
+ALTER TABLE task_events
+    ALTER COLUMN from_status TYPE "TaskStatus_Type_New"
+        USING (from_status::text::"TaskStatus_Type_New"),
+    ALTER COLUMN to_status TYPE "TaskStatus_Type_New"
+        USING (to_status::text::"TaskStatus_Type_New");
+
+DROP TYPE "TaskStatus_Type";
+
+ALTER TYPE "TaskStatus_Type_New" RENAME TO "TaskStatus_Type";
@@ -0,0 +1,6 @@
+ALTER TYPE "TaskStatus_Type" ADD VALUE 'FINISHED::SUPERSEDED';
+
+ALTER TABLE tasks
+    ADD COLUMN "supersede_key" VARCHAR;
+
+CREATE INDEX tasks_supersede_key_idx ON tasks (supersede_key) WHERE supersede_key IS NOT NULL;
@@ -6,6 +6,7 @@ use serde_json::{Map, Value};
 use std::str::FromStr;
 use uuid::Uuid;
 use vickylib::database::entities::Database;
+use vickylib::database::entities::task_event::Actor;
 
 use crate::config::{Config, OIDCConfigResolved};
 use crate::errors::AppError;
@@ -37,9 +38,28 @@ impl<'r> request::FromRequest<'r> for AnyAuthGuard {
     }
 }
 
-pub struct MachineGuard {}
+pub struct MachineGuard {
+    pub name: String,
+}
 pub struct UserGuard(pub User);
 
+impl MachineGuard {
+    pub fn actor(&self) -> Actor {
+        Actor::Machine {
+            name: self.name.clone(),
+        }
+    }
+}
+
+impl AnyAuthGuard {
+    pub fn actor(&self) -> Actor {
+        match self {
+            AnyAuthGuard::User(UserGuard(user)) => Actor::User { id: user.id },
+            AnyAuthGuard::Machine(machine) => machine.actor(),
+        }
+    }
+}
+
 async fn extract_user_from_token(
     jwks_verifier: &State<RemoteJwksVerifier>,
     db: &Database,
@@ -152,10 +172,15 @@ impl<'r> request::FromRequest<'r> for MachineGuard {
             return request::Outcome::Forward(Status::Forbidden);
         };
 
-        let cfg_user = config.machines.iter().find(|x| *x == auth_header);
+        let cfg_machine = config
+            .machines
+            .iter()
+            .find(|machine| machine.token() == auth_header);
 
-        match cfg_user {
-            Some(_) => request::Outcome::Success(MachineGuard {}),
+        match cfg_machine {
+            Some(machine) => request::Outcome::Success(MachineGuard {
+                name: machine.name(),
+            }),
             None => request::Outcome::Error((Status::Forbidden, ())),
         }
     }
@@ -3,7 +3,9 @@ use log::info;
 use rocket::figment::providers::{Env, Format, Toml};
 use rocket::figment::{Figment, Profile};
 use rocket::serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use vickylib::s3::client::S3Client;
+use vickylib::vicky::scheduler::SchedulerConfig;
 
 #[derive(Deserialize)]
 pub struct S3Config {
@@ -32,12 +34,64 @@ pub struct WebConfig {
     pub client_id: String,
 }
 
+/// A machine token, optionally with a name that is recorded as the actor of its changes.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum MachineConfig {
+    Token(String),
+    Named { name: String, token: String },
+}
+
+impl MachineConfig {
+    pub fn token(&self) -> &str {
+        match self {
+            MachineConfig::Token(token) => token,
+            MachineConfig::Named { token, .. } => token,
+        }
+    }
+
+    /// The name recorded as the actor of the changes of the machine. Unnamed machines are named
+    /// after a hash of their token, which doesn't reveal the token and survives reordering the
+    /// config.
+    pub fn name(&self) -> String {
+        match self {
+            MachineConfig::Token(token) => {
+                let fingerprint: String = Sha256::digest(token.as_bytes())[..6]
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect();
+                format!("machine {fingerprint}")
+            }
+            MachineConfig::Named { name, .. } => name.clone(),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct Config {
-    pub machines: Vec<String>,
+    pub machines: Vec<MachineConfig>,
     pub s3_config: S3Config,
     pub oidc_config: OIDCConfig,
     pub web_config: WebConfig,
+
+    /// Seconds a task may run if it doesn't specify its own maximum runtime.
+    #[serde(default = "default_max_runtime_sec")]
+    pub default_max_runtime_sec: i64,
+
+    /// Whether cancelling a task poisons its locks, like a failed task would.
+    #[serde(default = "default_poison_locks_on_cancel")]
+    pub poison_locks_on_cancel: bool,
+
+    #[serde(flatten)]
+    pub scheduler: SchedulerConfig,
+}
+
+fn default_max_runtime_sec() -> i64 {
+    6 * 60 * 60
+}
+
+fn default_poison_locks_on_cancel() -> bool {
+    true
 }
 
 pub fn build_rocket_config() -> Figment {
@@ -95,3 +149,16 @@ impl OIDCConfigResolved {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::MachineConfig;
+
+    #[test]
+    fn unnamed_machine_is_named_after_its_token_hash() {
+        let machine = MachineConfig::Token("abc1234".to_string());
+
+        assert_eq!(machine.name(), "machine 36f583dd16f4");
+        assert!(!machine.name().contains("abc1234"));
+    }
+}
@@ -1,11 +1,28 @@
 use crate::auth::AnyAuthGuard;
 use crate::errors::AppError;
+use rocket::http::Status;
 use rocket::serde::json::Json;
 use rocket::{get, patch};
 use uuid::Uuid;
 use vickylib::database::entities::lock::PoisonedLock;
 use vickylib::database::entities::{Database, Lock};
 
+/// Rejects semaphores without a capacity, and semaphores whose capacity differs from the one the
+/// pending tasks holding them already agreed on.
+pub async fn check_semaphore_capacities(db: &Database, locks: &[Lock]) -> Result<(), AppError> {
+    for lock in locks.iter().filter(|lock| lock.kind.is_semaphore()) {
+        if lock.capacity.unwrap_or(0) == 0 {
+            return Err(AppError::HttpError(Status::BadRequest));
+        }
+
+        let capacity = db.get_semaphore_capacity(lock.name.clone()).await?;
+        if capacity.is_some_and(|capacity| capacity as usize != lock.capacity()) {
+            return Err(AppError::HttpError(Status::Conflict));
+        }
+    }
+    Ok(())
+}
+
 #[get("/poisoned")]
 pub async fn locks_get_poisoned(
     db: Database,
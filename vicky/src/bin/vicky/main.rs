@@ -3,13 +3,19 @@ use crate::events::{GlobalEvent, get_global_events};
 use crate::locks::{
     locks_get_active, locks_get_detailed_poisoned, locks_get_poisoned, locks_unlock,
 };
+use crate::schedules::{
+    schedules_add, schedules_delete, schedules_get, schedules_get_specific, schedules_update,
+};
 use crate::startup::Result;
 use crate::tasks::{
     tasks_add, tasks_cancel, tasks_claim, tasks_confirm, tasks_count, tasks_download_logs,
-    tasks_finish, tasks_get, tasks_get_logs, tasks_get_specific, tasks_heartbeat, tasks_put_logs,
+    tasks_finish, tasks_get, tasks_get_approvals, tasks_get_edits, tasks_get_history,
+    tasks_get_logs, tasks_get_readiness, tasks_get_specific, tasks_heartbeat, tasks_patch,
+    tasks_put_logs, tasks_reject, tasks_rerun,
 };
 use crate::user::get_user;
 use crate::webconfig::get_web_config;
+use chrono::{TimeDelta, Utc};
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
 use errors::AppError;
 use jwtk::jwk::RemoteJwksVerifier;
@@ -31,6 +37,7 @@ mod config;
 mod errors;
 mod events;
 mod locks;
+mod schedules;
 mod startup;
 mod tasks;
 mod user;
@@ -108,6 +115,8 @@ async fn inner_main() -> Result<()> {
 
     let (tx_global_events, _rx_task_events) = broadcast::channel::<GlobalEvent>(5);
 
+    let default_max_runtime = TimeDelta::seconds(app_config.default_max_runtime_sec);
+
     let web_server = build_web_api(
         app_config,
         build_rocket,
@@ -115,7 +124,7 @@ async fn inner_main() -> Result<()> {
         jwks_verifier,
         s3_log_bucket_client,
         log_drain,
-        tx_global_events,
+        tx_global_events.clone(),
     )
     .await?;
 
@@ -126,6 +135,7 @@ async fn inner_main() -> Result<()> {
     let web_task =
         tokio::task::spawn(async move { web_server.launch().await.context(startup::LaunchErr) });
 
+    let schedule_db_pool = db_pool.clone();
     let task_timeout_sweeper = tokio::task::spawn(async move {
         let mut interval = tokio::time::interval(Duration::from_secs(10));
 
@@ -138,10 +148,11 @@ async fn inner_main() -> Result<()> {
                 continue;
             };
 
-            match db.perform_timeout_sweep().await {
-                Ok((0, 0)) => trace!("Performed timeout sweep"),
-                Ok((tasks_affected, locks_affected)) => warn!(
-                    "Timed out {tasks_affected} task(s) and poisoned {locks_affected} lock(s) after not receiving a heartbeat for {HEARTBEAT_TIMEOUT_SEC} seconds"
+            match db.perform_timeout_sweep(default_max_runtime).await {
+                Ok(sweep) if sweep.is_empty() => trace!("Performed timeout sweep"),
+                Ok(sweep) => warn!(
+                    "Timed out {} task(s) after not receiving a heartbeat for {HEARTBEAT_TIMEOUT_SEC} seconds and {} task(s) after exceeding their maximum runtime, poisoned {} lock(s)",
+                    sweep.heartbeat_timeouts, sweep.runtime_timeouts, sweep.poisoned_locks
                 ),
                 Err(e) => warn!(
                     "Could not perform sweep as the database could not be queried successfully: {e}"
@@ -150,9 +161,39 @@ async fn inner_main() -> Result<()> {
         }
     });
 
+    let schedule_runner = tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(10));
+
+        loop {
+            interval.tick().await;
+            let Some(db) = Database::get_one_from_pool(&schedule_db_pool).await else {
+                warn!(
+                    "Could not run schedules as no database connection could be retrieved from the pool"
+                );
+                continue;
+            };
+
+            match db.run_due_schedules(Utc::now()).await {
+                Ok((0, 0)) => trace!("Checked schedules"),
+                Ok((started, skipped)) => {
+                    info!(
+                        "Started {started} scheduled task(s), skipped {skipped} run(s) whose previous task is still pending"
+                    );
+                    if started > 0 {
+                        let _ = tx_global_events.send(GlobalEvent::TaskAdd);
+                    }
+                }
+                Err(e) => warn!(
+                    "Could not run schedules as the database could not be queried successfully: {e}"
+                ),
+            }
+        }
+    });
+
     select! {
         e = web_task => e.map(|_| ()).context(startup::JoinErr)?,
         _ = task_timeout_sweeper => panic!("Task timeout sweeper shouldn't exit"),
+        _ = schedule_runner => panic!("Schedule runner shouldn't exit"),
     }
 
     Ok(())
@@ -191,6 +232,7 @@ async fn build_web_api(
                 tasks_count,
                 tasks_get,
                 tasks_get_specific,
+                tasks_get_readiness,
                 tasks_claim,
                 tasks_heartbeat,
                 tasks_finish,
@@ -199,7 +241,23 @@ async fn build_web_api(
                 tasks_put_logs,
                 tasks_download_logs,
                 tasks_confirm,
-                tasks_cancel
+                tasks_cancel,
+                tasks_reject,
+                tasks_rerun,
+                tasks_patch,
+                tasks_get_edits,
+                tasks_get_history,
+                tasks_get_approvals
+            ],
+        )
+        .mount(
+            "/api/v1/schedules",
+            routes![
+                schedules_get,
+                schedules_get_specific,
+                schedules_add,
+                schedules_update,
+                schedules_delete
             ],
         )
         .mount(
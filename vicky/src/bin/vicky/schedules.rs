@@ -0,0 +1,130 @@
+use chrono::{DateTime, Utc};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{delete, get, post, put};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use vickylib::database::entities::schedule::parse_cron;
+use vickylib::database::entities::task::FlakeRef;
+use vickylib::database::entities::{Database, Lock, Schedule};
+
+use crate::auth::{AnyAuthGuard, MachineGuard};
+use crate::errors::AppError;
+use crate::locks::check_semaphore_capacities;
+
+macro_rules! schedule_or_not_found {
+    ($db:expr, $id:expr) => {
+        $db.get_schedule($id)
+            .await?
+            .ok_or(AppError::HttpError(Status::NotFound))
+    };
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct RoScheduleNew {
+    display_name: String,
+    cron: String,
+    flake_ref: FlakeRef,
+    locks: Vec<Lock>,
+    features: Vec<String>,
+    group: Option<String>,
+}
+
+impl RoScheduleNew {
+    /// Rejects invalid cron expressions, conflicting locks and semaphore capacities the pending
+    /// tasks disagree with, and plans the next run.
+    async fn into_schedule(
+        self,
+        db: &Database,
+        id: Uuid,
+        created_at: DateTime<Utc>,
+        last_task_id: Option<Uuid>,
+    ) -> Result<Schedule, AppError> {
+        let cron = parse_cron(&self.cron).map_err(|_| AppError::HttpError(Status::BadRequest))?;
+
+        let schedule = Schedule {
+            id,
+            display_name: self.display_name,
+            cron: self.cron,
+            flake_ref: self.flake_ref,
+            locks: self.locks,
+            features: self.features,
+            group: self.group,
+            created_at,
+            next_run_at: cron.after(&Utc::now()).next(),
+            last_task_id,
+        };
+
+        if schedule.instantiate().is_none() {
+            return Err(AppError::HttpError(Status::Conflict));
+        }
+        check_semaphore_capacities(db, &schedule.locks).await?;
+
+        Ok(schedule)
+    }
+}
+
+#[get("/")]
+pub async fn schedules_get(
+    db: Database,
+    _auth: AnyAuthGuard,
+) -> Result<Json<Vec<Schedule>>, AppError> {
+    let schedules = db.get_all_schedules().await?;
+    Ok(Json(schedules))
+}
+
+#[get("/<id>")]
+pub async fn schedules_get_specific(
+    id: Uuid,
+    db: Database,
+    _auth: AnyAuthGuard,
+) -> Result<Json<Schedule>, AppError> {
+    let schedule = schedule_or_not_found!(db, id)?;
+    Ok(Json(schedule))
+}
+
+#[post("/", data = "<schedule>")]
+pub async fn schedules_add(
+    schedule: Json<RoScheduleNew>,
+    db: Database,
+    _machine: MachineGuard,
+) -> Result<Json<Schedule>, AppError> {
+    let new_schedule = schedule
+        .into_inner()
+        .into_schedule(&db, Uuid::new_v4(), Utc::now(), None)
+        .await?;
+
+    db.put_schedule(new_schedule.clone()).await?;
+
+    Ok(Json(new_schedule))
+}
+
+#[put("/<id>", data = "<schedule>")]
+pub async fn schedules_update(
+    id: Uuid,
+    schedule: Json<RoScheduleNew>,
+    db: Database,
+    _machine: MachineGuard,
+) -> Result<Json<Schedule>, AppError> {
+    let existing = schedule_or_not_found!(db, id)?;
+    let updated = schedule
+        .into_inner()
+        .into_schedule(&db, existing.id, existing.created_at, existing.last_task_id)
+        .await?;
+
+    db.update_schedule(updated.clone()).await?;
+
+    Ok(Json(updated))
+}
+
+#[delete("/<id>")]
+pub async fn schedules_delete(
+    id: Uuid,
+    db: Database,
+    _machine: MachineGuard,
+) -> Result<(), AppError> {
+    match db.delete_schedule(id).await? {
+        0 => Err(AppError::HttpError(Status::NotFound)),
+        _ => Ok(()),
+    }
+}
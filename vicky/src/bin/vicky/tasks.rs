@@ -1,16 +1,25 @@
-use chrono::{TimeDelta, Utc};
+use chrono::serde::ts_seconds_option;
+use chrono::{DateTime, TimeDelta, Utc};
 use log::{error, warn};
 use rocket::http::Status;
 use rocket::response::stream::{Event, EventStream};
-use rocket::{State, get, post, serde::json::Json};
-use serde::{Deserialize, Serialize};
+use rocket::response::{self, Responder};
+use rocket::{Request, State, get, patch, post, request, serde::json::Json};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
 use std::time;
 use tokio::sync::broadcast::{self, error::TryRecvError};
 use uuid::Uuid;
-use vickylib::database::entities::task::HEARTBEAT_TIMEOUT_SEC;
-use vickylib::database::entities::task::{FlakeRef, TaskResult, TaskStatus};
+use vickylib::database::entities::task::{
+    FlakeRef, Heartbeat, RetryPolicy, TaskRejection, TaskResult, TaskStatus,
+};
+use vickylib::database::entities::task::{HEARTBEAT_TIMEOUT_SEC, IDEMPOTENCY_KEY_HEADER};
+use vickylib::database::entities::task_approval::{ApprovalOutcome, TaskApproval};
+use vickylib::database::entities::task_edit::TaskEdit;
+use vickylib::database::entities::task_event::TaskEvent;
 use vickylib::database::entities::{Database, Lock, Task};
-use vickylib::query::FilterParams;
+use vickylib::query::{FilterParams, NEXT_CURSOR_HEADER, TaskCursor};
+use vickylib::vicky::readiness::TaskReadiness;
 use vickylib::{
     errors::VickyError, logs::LogDrain, s3::client::S3Client, vicky::scheduler::Scheduler,
 };
@@ -31,10 +40,12 @@ macro_rules! task_or_not_found {
 }
 
 use crate::auth::AnyAuthGuard;
+use crate::config::Config;
 use crate::{
     auth::{MachineGuard, UserGuard},
     errors::AppError,
     events::GlobalEvent,
+    locks::check_semaphore_capacities,
 };
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -46,6 +57,70 @@ pub struct RoTaskNew {
     locks: Vec<Lock>,
     features: Vec<String>,
     group: Option<String>,
+    #[serde(default)]
+    priority: i32,
+    #[serde(default)]
+    depends_on: Vec<Uuid>,
+    #[serde(default, with = "ts_seconds_option")]
+    not_before: Option<DateTime<Utc>>,
+    #[serde(default)]
+    retry_policy: Option<RetryPolicy>,
+    #[serde(default)]
+    max_runtime: Option<u32>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    /// Tasks requiring approvals always need to be confirmed.
+    #[serde(default)]
+    required_approvals: u32,
+    /// Alternative to the `Idempotency-Key` header.
+    #[serde(default)]
+    idempotency_key: Option<String>,
+    #[serde(default)]
+    supersede_key: Option<String>,
+}
+
+/// The `Idempotency-Key` header, if it was sent.
+pub struct IdempotencyKey(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> request::FromRequest<'r> for IdempotencyKey {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let key = request.headers().get_one(IDEMPOTENCY_KEY_HEADER);
+        request::Outcome::Success(IdempotencyKey(key.map(String::from)))
+    }
+}
+
+/// Fields that are left out stay unchanged.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct RoTaskPatch {
+    display_name: Option<String>,
+    flake_args: Option<Vec<String>>,
+    locks: Option<Vec<Lock>>,
+    features: Option<Vec<String>>,
+    /// `null` removes the task from its group.
+    #[serde(default, deserialize_with = "present")]
+    group: Option<Option<String>>,
+    priority: Option<i32>,
+}
+
+/// Distinguishes a field set to `null` from a missing one.
+fn present<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+    deserializer: D,
+) -> Result<Option<T>, D::Error> {
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct RoTaskReject {
+    comment: String,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoTaskRerun {
+    #[serde(default)]
+    clear_poison: bool,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -59,6 +134,15 @@ pub struct RoTask {
     status: TaskStatus,
 }
 
+impl From<&Task> for RoTask {
+    fn from(task: &Task) -> Self {
+        RoTask {
+            id: task.id,
+            status: task.status,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct RoTaskFinish {
     result: TaskResult,
@@ -74,19 +158,40 @@ pub struct Count {
     count: i64,
 }
 
+fn parse_task_statuses(status: &[String]) -> Result<Vec<TaskStatus>, AppError> {
+    status
+        .iter()
+        .map(|status| TaskStatus::try_from(status.as_str()))
+        .collect::<Result<_, _>>()
+        .map_err(|_| AppError::HttpError(Status::BadRequest))
+}
+
+/// A page of tasks. If there may be further tasks, the cursor of the next page is sent
+/// in the `Next-Cursor` header.
+pub struct TaskPage {
+    tasks: Vec<Task>,
+    next_cursor: Option<TaskCursor>,
+}
+
+impl<'r> Responder<'r, 'static> for TaskPage {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Json(self.tasks).respond_to(req)?;
+        if let Some(cursor) = self.next_cursor {
+            response.set_raw_header(NEXT_CURSOR_HEADER, cursor.encode());
+        }
+        Ok(response)
+    }
+}
+
 #[get("/count?<status>&<filter_params..>")]
 pub async fn tasks_count(
     db: Database,
     _auth: AnyAuthGuard,
-    status: Option<String>,
+    status: Vec<String>,
     filter_params: Option<FilterParams>,
 ) -> Result<Json<Count>, AppError> {
-    let task_status: Option<TaskStatus> = status
-        .as_deref()
-        .map(TaskStatus::try_from)
-        .transpose()
-        .map_err(|_| AppError::HttpError(Status::BadRequest))?;
-    let tasks_count = db.count_all_tasks(task_status, filter_params).await?;
+    let task_statuses = parse_task_statuses(&status)?;
+    let tasks_count = db.count_all_tasks(task_statuses, filter_params).await?;
     let c: Count = Count { count: tasks_count };
     Ok(Json(c))
 }
@@ -95,18 +200,25 @@ pub async fn tasks_count(
 pub async fn tasks_get(
     db: Database,
     _auth: AnyAuthGuard,
-    status: Option<String>,
+    status: Vec<String>,
     filter_params: Option<FilterParams>,
-) -> Result<Json<Vec<Task>>, AppError> {
-    let task_status: Option<TaskStatus> = status
-        .as_deref()
-        .map(TaskStatus::try_from)
-        .transpose()
-        .map_err(|_| AppError::HttpError(Status::BadRequest))?;
+) -> Result<TaskPage, AppError> {
+    let task_statuses = parse_task_statuses(&status)?;
+    let filter_params = filter_params.unwrap_or_default();
+    // a cursor already decides where the page starts, an offset on top of it would skip tasks
+    if filter_params.cursor.is_some()
+        && (filter_params.task_cursor().is_none()
+            || !filter_params.is_sorted_by_creation()
+            || filter_params.offset.is_some())
+    {
+        return Err(AppError::HttpError(Status::BadRequest));
+    }
+
     let tasks: Vec<Task> = db
-        .get_all_tasks_filtered(task_status, filter_params)
+        .get_all_tasks_filtered(task_statuses, filter_params.clone())
         .await?;
-    Ok(Json(tasks))
+    let next_cursor = filter_params.next_cursor(&tasks);
+    Ok(TaskPage { tasks, next_cursor })
 }
 
 #[get("/<id>")]
@@ -119,6 +231,25 @@ pub async fn tasks_get_specific(
     Ok(Json(tasks))
 }
 
+#[get("/<id>/readiness?<features>")]
+pub async fn tasks_get_readiness(
+    id: Uuid,
+    db: Database,
+    _auth: AnyAuthGuard,
+    features: Vec<String>,
+    config: &State<Config>,
+) -> Result<Json<TaskReadiness>, AppError> {
+    let task: Task = task_or_not_found!(db, id)?;
+
+    let tasks = db.get_pending_tasks().await?;
+    let poisoned_locks = db.get_poisoned_locks().await?;
+    let scheduler = Scheduler::new(&tasks, &poisoned_locks, &features)
+        .map_err(|x| VickyError::Scheduler { source: x })?
+        .with_config(config.scheduler.clone());
+
+    Ok(Json(scheduler.explain_task_readiness(&task)))
+}
+
 #[get("/<id>/logs?<start>")]
 pub async fn tasks_get_logs<'a>(
     id: Uuid,
@@ -251,31 +382,30 @@ pub async fn tasks_claim(
     db: Database,
     features: Json<RoTaskClaim>,
     global_events: &State<broadcast::Sender<GlobalEvent>>,
-    _machine: MachineGuard,
+    machine: MachineGuard,
+    config: &State<Config>,
 ) -> Result<Json<Option<Task>>, AppError> {
-    let tasks = db.get_all_tasks().await?;
-    let poisoned_locks = db.get_poisoned_locks().await?;
-    let scheduler = Scheduler::new(&tasks, &poisoned_locks, &features.features)
-        .map_err(|x| VickyError::Scheduler { source: x })?;
-    let next_task = scheduler.get_next_task();
-
-    match next_task {
-        Some(next_task) => {
-            let mut task: Task = task_or_not_found!(db, next_task.id)?;
-            task.status = TaskStatus::Running;
-            task.claimed_at = Some(Utc::now());
-            task.last_heartbeat = task.claimed_at;
-
-            db.update_task(task.clone()).await?;
-            global_events.send(GlobalEvent::TaskUpdate { uuid: task.id })?;
-            Ok(Json(Some(task)))
-        }
-        None => Ok(Json(None)),
+    let next_task = db
+        .claim_next_task(
+            features.into_inner().features,
+            config.scheduler.clone(),
+            machine.actor(),
+        )
+        .await?;
+
+    if let Some(task) = &next_task {
+        global_events.send(GlobalEvent::TaskUpdate { uuid: task.id })?;
     }
+
+    Ok(Json(next_task))
 }
 
 #[post("/<id>/heartbeat")]
-pub async fn tasks_heartbeat(id: Uuid, db: Database, _auth: AnyAuthGuard) -> Result<(), AppError> {
+pub async fn tasks_heartbeat(
+    id: Uuid,
+    db: Database,
+    _auth: AnyAuthGuard,
+) -> Result<Json<Heartbeat>, AppError> {
     let task: Task = task_or_not_found!(db, id)?;
 
     if task.status != TaskStatus::Running {
@@ -288,7 +418,9 @@ pub async fn tasks_heartbeat(id: Uuid, db: Database, _auth: AnyAuthGuard) -> Res
 
     // TODO: Consider sending a global event here
 
-    Ok(())
+    Ok(Json(Heartbeat {
+        cancel_requested: task.cancel_requested_at.is_some(),
+    }))
 }
 
 #[post("/<id>/finish", format = "json", data = "<finish>")]
@@ -297,18 +429,44 @@ pub async fn tasks_finish(
     finish: Json<RoTaskFinish>,
     db: Database,
     global_events: &State<broadcast::Sender<GlobalEvent>>,
-    _machine: MachineGuard,
+    machine: MachineGuard,
     log_drain: &State<LogDrain>,
+    config: &State<Config>,
 ) -> Result<Json<Task>, AppError> {
+    // results that only the server assigns can't be reported
+    if matches!(
+        finish.result,
+        TaskResult::DependencyFailed | TaskResult::Superseded
+    ) {
+        return Err(AppError::HttpError(Status::BadRequest));
+    }
+
     let mut task: Task = task_or_not_found!(db, id)?;
 
+    if task.status != TaskStatus::Running {
+        log_drain.finish_logs(id).await?;
+        return Err(AppError::HttpError(Status::Conflict));
+    }
+
     task.finish(finish.result);
 
-    db.update_task(task.clone()).await?;
+    // the task may have been timed out in the meantime, which must not be overwritten
+    let finished = db
+        .finish_task(task.clone(), config.poison_locks_on_cancel, machine.actor())
+        .await?;
+    if finished == 0 {
+        log_drain.finish_logs(id).await?;
+        return Err(AppError::HttpError(Status::Conflict));
+    }
 
     let log_error = log_drain.finish_logs(id).await;
 
+    // cancelled tasks end up as cancelled, whatever they reported
+    let task = task_or_not_found!(db, id)?;
     global_events.send(GlobalEvent::TaskUpdate { uuid: task.id })?;
+    if task.status == TaskStatus::Finished(finish.result) && task.will_retry(finish.result) {
+        global_events.send(GlobalEvent::TaskAdd)?;
+    }
 
     // only handle log error here so that the UI gets the event at the right time
     log_error?;
@@ -319,38 +477,112 @@ pub async fn tasks_finish(
 #[post("/", data = "<task>")]
 pub async fn tasks_add(
     task: Json<RoTaskNew>,
+    idempotency_key: IdempotencyKey,
     db: Database,
     global_events: &State<broadcast::Sender<GlobalEvent>>,
-    _machine: MachineGuard,
+    auth: AnyAuthGuard,
 ) -> Result<Json<RoTask>, AppError> {
-    let status = if task.needs_confirmation {
-        TaskStatus::NeedsUserValidation
-    } else {
-        TaskStatus::New
+    let task = task.into_inner();
+
+    let idempotency_key = match (idempotency_key.0, task.idempotency_key) {
+        (Some(header), Some(field)) if header != field => {
+            return Err(AppError::HttpError(Status::BadRequest));
+        }
+        (header, field) => header.or(field),
     };
+    if let Some(key) = &idempotency_key {
+        if key.is_empty() {
+            return Err(AppError::HttpError(Status::BadRequest));
+        }
+        // a repeated submission, the original task is returned
+        if let Some(original) = db.get_task_by_idempotency_key(key.clone()).await? {
+            return Ok(Json(RoTask::from(&original)));
+        }
+    }
 
-    let task = task.into_inner();
+    if task
+        .retry_policy
+        .as_ref()
+        .is_some_and(|policy| !policy.is_valid())
+        || task.max_runtime == Some(0)
+        || task
+            .labels
+            .keys()
+            .any(|key| key.is_empty() || key.contains('='))
+        || task.supersede_key.as_deref() == Some("")
+    {
+        return Err(AppError::HttpError(Status::BadRequest));
+    }
+
+    check_semaphore_capacities(&db, &task.locks).await?;
+
+    let needs_confirmation = task.needs_confirmation || task.required_approvals > 0;
+
+    // a retried dependency is replaced by its latest attempt
+    let mut dependency_failed = false;
+    let mut depends_on = Vec::with_capacity(task.depends_on.len());
+    for dependency_id in task.depends_on {
+        let dependency: Task = db
+            .get_latest_attempt(dependency_id)
+            .await?
+            .ok_or(AppError::HttpError(Status::BadRequest))?;
+        dependency_failed |= dependency.status.is_failed();
+        depends_on.push(dependency.id);
+    }
+    depends_on.sort();
+    depends_on.dedup();
+
+    let (status, finished_at) = if dependency_failed {
+        (
+            TaskStatus::Finished(TaskResult::DependencyFailed),
+            Some(Utc::now()),
+        )
+    } else if needs_confirmation {
+        (TaskStatus::NeedsUserValidation, None)
+    } else {
+        (TaskStatus::New, None)
+    };
 
     let task = Task::builder()
         .status(status)
+        .maybe_finished_at(finished_at)
         .display_name(task.display_name)
         .flake(task.flake_ref.flake)
         .flake_args(task.flake_ref.args)
         .locks(task.locks)
         .requires_features(task.features)
         .maybe_group(task.group)
+        .priority(task.priority)
+        .dependencies(depends_on)
+        .maybe_not_before(task.not_before)
+        .maybe_retry_policy(task.retry_policy)
+        .maybe_max_runtime(task.max_runtime)
+        .needs_confirmation(needs_confirmation)
+        .required_approvals(task.required_approvals)
+        .labels(task.labels)
+        .maybe_idempotency_key(idempotency_key.clone())
+        .maybe_supersede_key(task.supersede_key)
+        .maybe_created_by(match &auth {
+            AnyAuthGuard::User(UserGuard(user)) => Some(user.id),
+            AnyAuthGuard::Machine(_) => None,
+        })
         .build();
 
     let Ok(task) = task else {
         return Err(AppError::HttpError(Status::Conflict));
     };
 
-    let ro_task = RoTask {
-        id: task.id,
-        status,
-    };
+    let ro_task = RoTask::from(&task);
 
-    db.put_task(task).await?;
+    if db.put_task(task).await? == 0 {
+        // a concurrent submission with the same idempotency key was inserted first
+        let key = idempotency_key.ok_or(AppError::HttpError(Status::Conflict))?;
+        let original = db
+            .get_task_by_idempotency_key(key)
+            .await?
+            .ok_or(AppError::HttpError(Status::Conflict))?;
+        return Ok(Json(RoTask::from(&original)));
+    }
     global_events.send(GlobalEvent::TaskAdd)?;
 
     Ok(Json(ro_task))
@@ -361,7 +593,7 @@ pub async fn tasks_confirm(
     id: Uuid,
     db: Database,
     global_events: &State<broadcast::Sender<GlobalEvent>>,
-    _auth: AnyAuthGuard,
+    auth: AnyAuthGuard,
 ) -> Result<Json<Task>, AppError> {
     let mut task = task_or_not_found!(db, id)?;
 
@@ -371,35 +603,230 @@ pub async fn tasks_confirm(
         return Err(AppError::HttpError(Status::Conflict));
     }
 
+    if task.required_approvals > 0 {
+        return approve_task(task, &db, global_events, auth).await;
+    }
+
+    // the task may have been confirmed or cancelled in the meantime
+    if db.confirm_task(id, auth.actor()).await? == 0 {
+        return Err(AppError::HttpError(Status::Conflict));
+    }
     task.status = TaskStatus::New;
-    db.update_task(task.clone()).await?;
     global_events.send(GlobalEvent::TaskUpdate { uuid: task.id })?;
 
     Ok(Json(task))
 }
 
-#[post("/<id>/cancel")]
-pub async fn tasks_cancel(
+/// Records the approval of a user, the task is confirmed once it has enough of them.
+async fn approve_task(
+    task: Task,
+    db: &Database,
+    global_events: &State<broadcast::Sender<GlobalEvent>>,
+    auth: AnyAuthGuard,
+) -> Result<Json<Task>, AppError> {
+    let AnyAuthGuard::User(UserGuard(user)) = &auth else {
+        return Err(AppError::HttpError(Status::Forbidden));
+    };
+
+    match db.approve_task(task.clone(), user.id).await? {
+        ApprovalOutcome::Recorded | ApprovalOutcome::Confirmed => {}
+        ApprovalOutcome::Forbidden => return Err(AppError::HttpError(Status::Forbidden)),
+        ApprovalOutcome::NotAwaitingConfirmation => {
+            return Err(AppError::HttpError(Status::Conflict));
+        }
+    }
+    global_events.send(GlobalEvent::TaskUpdate { uuid: task.id })?;
+
+    let task = task_or_not_found!(db, task.id)?;
+    Ok(Json(task))
+}
+
+#[patch("/<id>", format = "json", data = "<patch>")]
+pub async fn tasks_patch(
     id: Uuid,
+    patch: Json<RoTaskPatch>,
     db: Database,
     global_events: &State<broadcast::Sender<GlobalEvent>>,
-    _auth: AnyAuthGuard,
+    auth: AnyAuthGuard,
 ) -> Result<Json<Task>, AppError> {
+    let patch = patch.into_inner();
     let mut task = task_or_not_found!(db, id)?;
 
-    if task.status == TaskStatus::Finished(TaskResult::Cancel) {
-        return Err(AppError::TaskAlreadyCancelled);
-    } else if task.status != TaskStatus::NeedsUserValidation {
+    if task.status != TaskStatus::NeedsUserValidation {
+        return Err(AppError::HttpError(Status::Conflict));
+    }
+
+    if let Some(locks) = &patch.locks
+        && Task::builder().locks(locks.clone()).check_lock_conflict()
+    {
+        return Err(AppError::HttpError(Status::Conflict));
+    }
+    if let Some(locks) = &patch.locks {
+        check_semaphore_capacities(&db, locks).await?;
+    }
+
+    let edited_by = match auth {
+        AnyAuthGuard::User(UserGuard(user)) => Some(user.id),
+        AnyAuthGuard::Machine(_) => None,
+    };
+
+    let mut edit = TaskEdit::new(task.id, edited_by);
+    edit.record("display_name", &mut task.display_name, patch.display_name);
+    edit.record("flake_args", &mut task.flake_ref.args, patch.flake_args);
+    edit.record("locks", &mut task.locks, patch.locks);
+    edit.record("features", &mut task.features, patch.features);
+    edit.record("group", &mut task.group, patch.group);
+    edit.record("priority", &mut task.priority, patch.priority);
+
+    if edit.is_empty() {
+        return Ok(Json(task));
+    }
+
+    // the task may have been confirmed or cancelled in the meantime
+    if db.edit_task(task.clone(), edit).await? == 0 {
         return Err(AppError::HttpError(Status::Conflict));
     }
 
-    task.status = TaskStatus::Finished(TaskResult::Cancel);
-    db.update_task(task.clone()).await?;
     global_events.send(GlobalEvent::TaskUpdate { uuid: task.id })?;
 
     Ok(Json(task))
 }
 
+#[get("/<id>/edits")]
+pub async fn tasks_get_edits(
+    id: Uuid,
+    db: Database,
+    _auth: AnyAuthGuard,
+) -> Result<Json<Vec<TaskEdit>>, AppError> {
+    task_or_not_found!(db, id)?;
+
+    let edits = db.get_task_edits(id).await?;
+    Ok(Json(edits))
+}
+
+#[get("/<id>/approvals")]
+pub async fn tasks_get_approvals(
+    id: Uuid,
+    db: Database,
+    _auth: AnyAuthGuard,
+) -> Result<Json<Vec<TaskApproval>>, AppError> {
+    task_or_not_found!(db, id)?;
+
+    let approvals = db.get_task_approvals(id).await?;
+    Ok(Json(approvals))
+}
+
+#[get("/<id>/history")]
+pub async fn tasks_get_history(
+    id: Uuid,
+    db: Database,
+    _auth: AnyAuthGuard,
+) -> Result<Json<Vec<TaskEvent>>, AppError> {
+    task_or_not_found!(db, id)?;
+
+    let events = db.get_task_events(id).await?;
+    Ok(Json(events))
+}
+
+#[post("/<id>/rerun", data = "<rerun>")]
+pub async fn tasks_rerun(
+    id: Uuid,
+    rerun: Option<Json<RoTaskRerun>>,
+    db: Database,
+    global_events: &State<broadcast::Sender<GlobalEvent>>,
+    auth: AnyAuthGuard,
+) -> Result<Json<RoTask>, AppError> {
+    let rerun = rerun.map(Json::into_inner).unwrap_or_default();
+    let task = task_or_not_found!(db, id)?;
+
+    if !task.status.is_finished() {
+        return Err(AppError::HttpError(Status::Conflict));
+    }
+
+    let created_by = match auth {
+        AnyAuthGuard::User(UserGuard(user)) => Some(user.id),
+        AnyAuthGuard::Machine(_) => None,
+    };
+
+    let Some(new_task) = db.rerun_task(task, rerun.clear_poison, created_by).await? else {
+        return Err(AppError::HttpError(Status::Conflict));
+    };
+
+    global_events.send(GlobalEvent::TaskAdd)?;
+
+    Ok(Json(RoTask {
+        id: new_task.id,
+        status: new_task.status,
+    }))
+}
+
+#[post("/<id>/cancel")]
+pub async fn tasks_cancel(
+    id: Uuid,
+    db: Database,
+    global_events: &State<broadcast::Sender<GlobalEvent>>,
+    config: &State<Config>,
+    auth: AnyAuthGuard,
+) -> Result<Json<Task>, AppError> {
+    let task = task_or_not_found!(db, id)?;
+
+    match task.status {
+        TaskStatus::Finished(TaskResult::Cancel) => return Err(AppError::TaskAlreadyCancelled),
+        // the fairy kills running tasks and finishes them as cancelled
+        TaskStatus::Running => {
+            db.request_task_cancellation(id, auth.actor()).await?;
+        }
+        TaskStatus::NeedsUserValidation => {
+            db.cancel_task(id, config.poison_locks_on_cancel, auth.actor())
+                .await?;
+        }
+        _ => return Err(AppError::HttpError(Status::Conflict)),
+    }
+
+    global_events.send(GlobalEvent::TaskUpdate { uuid: id })?;
+
+    let task = task_or_not_found!(db, id)?;
+    Ok(Json(task))
+}
+
+#[post("/<id>/reject", format = "json", data = "<reject>")]
+pub async fn tasks_reject(
+    id: Uuid,
+    reject: Json<RoTaskReject>,
+    db: Database,
+    global_events: &State<broadcast::Sender<GlobalEvent>>,
+    auth: AnyAuthGuard,
+) -> Result<Json<Task>, AppError> {
+    let comment = reject.into_inner().comment.trim().to_string();
+    if comment.is_empty() {
+        return Err(AppError::HttpError(Status::BadRequest));
+    }
+
+    let task = task_or_not_found!(db, id)?;
+    if task.status != TaskStatus::NeedsUserValidation {
+        return Err(AppError::HttpError(Status::Conflict));
+    }
+
+    let rejection = TaskRejection {
+        comment,
+        rejected_at: Utc::now(),
+        rejected_by: match &auth {
+            AnyAuthGuard::User(UserGuard(user)) => Some(user.id),
+            AnyAuthGuard::Machine(_) => None,
+        },
+    };
+
+    // the task may have been confirmed or cancelled in the meantime
+    if db.reject_task(id, rejection, auth.actor()).await? == 0 {
+        return Err(AppError::HttpError(Status::Conflict));
+    }
+
+    global_events.send(GlobalEvent::TaskUpdate { uuid: id })?;
+
+    let task = task_or_not_found!(db, id)?;
+    Ok(Json(task))
+}
+
 // only returns the task back if the task is in a running state and not timed out or finished
 #[allow(unused)]
 async fn maybe_timeout_task(task: Task, db: &mut Database) -> Result<Option<Task>, AppError> {
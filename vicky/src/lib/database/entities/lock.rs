@@ -8,6 +8,12 @@
 //! - Task failed: locks are poisoned until manually cleared.
 //! - Task succeeded: locks are free again.
 //! - Tasks are scheduled in queue order where FI is also FO
+//! - Semaphore locks can be held by up to `capacity` running tasks at once. They conflict with
+//!   every other lock kind like a write lock does, and with semaphores on a parent or child path.
+//!   All holders of a semaphore have to agree on its capacity.
+//! - Lock names are paths separated by `/`. A lock on `prod` also covers `prod/router1`, so both
+//!   are checked against each other with the same rules. Siblings like `prod/router1` and
+//!   `prod/router2` don't interact.
 
 use diesel::{AsExpression, FromSqlRow};
 use serde::{Deserialize, Serialize};
@@ -38,9 +44,14 @@ pub enum LockKind {
     Read,
     Write,
     Clean,
+    Semaphore,
 }
 
 impl LockKind {
+    pub const fn is_read(&self) -> bool {
+        matches!(self, LockKind::Read)
+    }
+
     pub const fn is_write(&self) -> bool {
         matches!(self, LockKind::Write)
     }
@@ -48,6 +59,15 @@ impl LockKind {
     pub const fn is_cleanup(&self) -> bool {
         matches!(self, LockKind::Clean)
     }
+
+    pub const fn is_semaphore(&self) -> bool {
+        matches!(self, LockKind::Semaphore)
+    }
+
+    /// Lock kinds that can't be shared with readers
+    pub const fn is_exclusive(&self) -> bool {
+        matches!(self, LockKind::Write | LockKind::Semaphore)
+    }
 }
 
 impl TryFrom<&str> for LockKind {
@@ -58,6 +78,7 @@ impl TryFrom<&str> for LockKind {
             "READ" => Ok(Self::Read),
             "WRITE" => Ok(Self::Write),
             "CLEAN" => Ok(Self::Clean),
+            "SEMAPHORE" => Ok(Self::Semaphore),
             _ => Err("Unexpected lock type received."),
         }
     }
@@ -70,6 +91,8 @@ pub struct Lock {
     pub kind: LockKind,
     #[serde(rename = "poisoned")]
     pub poisoned_by: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<u32>,
 }
 
 impl Lock {
@@ -85,8 +108,15 @@ impl Lock {
         Self::new(name, LockKind::Clean)
     }
 
+    pub fn semaphore<S: Into<String>>(name: S, capacity: u32) -> Self {
+        Self {
+            capacity: Some(capacity),
+            ..Self::new(name, LockKind::Semaphore)
+        }
+    }
+
     pub fn is_conflicting(&self, other: &Lock) -> bool {
-        if self.name() != other.name() {
+        if !self.overlaps(other) {
             return false;
         }
 
@@ -94,7 +124,26 @@ impl Lock {
             return true;
         }
 
-        self.kind.is_write() || other.kind.is_write()
+        // holders of the same semaphore share it, their count is checked by the scheduler
+        if self.kind.is_semaphore() && other.kind.is_semaphore() && self.name == other.name {
+            return false;
+        }
+
+        self.kind.is_exclusive() || other.kind.is_exclusive()
+    }
+
+    /// How many running tasks may hold this lock at once
+    pub fn capacity(&self) -> usize {
+        match self.kind {
+            LockKind::Semaphore => self.capacity.unwrap_or(1) as usize,
+            LockKind::Read | LockKind::Write | LockKind::Clean => 1,
+        }
+    }
+
+    /// Whether both locks protect the same resource, either because their names are equal or
+    /// because one of them is a parent path of the other.
+    pub fn overlaps(&self, other: &Lock) -> bool {
+        lock_names_overlap(self.name(), other.name())
     }
 
     pub const fn poison(&mut self, by_task: &Uuid) {
@@ -118,10 +167,24 @@ impl Lock {
             name: name.into(),
             kind,
             poisoned_by: None,
+            capacity: None,
         }
     }
 }
 
+pub const LOCK_PATH_SEPARATOR: char = '/';
+
+/// Whether one lock name is the other one or one of its parents.
+pub fn lock_names_overlap(a: &str, b: &str) -> bool {
+    let a = a.trim_end_matches(LOCK_PATH_SEPARATOR);
+    let b = b.trim_end_matches(LOCK_PATH_SEPARATOR);
+    let (parent, child) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+
+    child
+        .strip_prefix(parent)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(LOCK_PATH_SEPARATOR))
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PoisonedLock {
     pub id: Uuid,
@@ -150,18 +213,18 @@ pub mod db_impl {
     use diesel::pg::PgValue;
     use diesel::prelude::*;
     use diesel::serialize::{IsNull, Output, ToSql};
-    use diesel::{SqlType, update};
+    use diesel::{QueryId, SqlType, update};
     use serde::Serialize;
     use std::io::Write;
     use uuid::Uuid;
 
     use crate::database::entities::lock::{Lock, LockKind, PoisonedLock};
     use crate::database::entities::task::TaskStatus;
-    use crate::database::entities::task::db_impl::DbTask;
+    use crate::database::entities::task::db_impl::{DbTask, PENDING_STATES};
     use crate::database::schema::{locks, tasks};
     use crate::errors::VickyError;
 
-    #[derive(SqlType)]
+    #[derive(SqlType, QueryId)]
     #[diesel(postgres_type(name = "LockKind_Type"))]
     pub struct LockKindSqlType;
 
@@ -190,6 +253,7 @@ pub mod db_impl {
         pub name: String,
         pub lock_type: LockKind,
         pub poisoned_by_task: Option<Uuid>,
+        pub capacity: Option<i32>,
     }
 
     #[derive(Insertable, Debug)]
@@ -199,6 +263,7 @@ pub mod db_impl {
         pub name: String,
         pub lock_type: LockKind,
         pub poisoned_by_task: Option<Uuid>,
+        pub capacity: Option<i32>,
     }
 
     impl NewDbLock {
@@ -208,6 +273,9 @@ pub mod db_impl {
                 name: lock.name.clone(),
                 lock_type: lock.kind,
                 poisoned_by_task: lock.poisoned_by,
+                capacity: lock
+                    .capacity
+                    .map(|capacity| i32::try_from(capacity).unwrap_or(i32::MAX)),
             }
         }
     }
@@ -218,6 +286,9 @@ pub mod db_impl {
                 name: lock.name,
                 kind: lock.lock_type,
                 poisoned_by: lock.poisoned_by_task,
+                capacity: lock
+                    .capacity
+                    .and_then(|capacity| u32::try_from(capacity).ok()),
             }
         }
     }
@@ -226,6 +297,8 @@ pub mod db_impl {
         fn get_poisoned_locks(&mut self) -> Result<Vec<Lock>, VickyError>;
         fn get_poisoned_locks_with_tasks(&mut self) -> Result<Vec<PoisonedLock>, VickyError>;
         fn get_active_locks(&mut self) -> Result<Vec<Lock>, VickyError>;
+        /// The capacity that pending tasks declared for the semaphore, if any of them holds it
+        fn get_semaphore_capacity(&mut self, name: &str) -> Result<Option<u32>, VickyError>;
         fn poison_all_locks_by_task(&mut self, task_id: Uuid) -> Result<usize, VickyError>;
         fn unlock_lock(&mut self, lock_uuid: &Uuid) -> Result<usize, VickyError>;
     }
@@ -274,6 +347,23 @@ pub mod db_impl {
             Ok(locks)
         }
 
+        fn get_semaphore_capacity(&mut self, name: &str) -> Result<Option<u32>, VickyError> {
+            let capacity = locks::table
+                .inner_join(tasks::table.on(locks::task_id.eq(tasks::id)))
+                .filter(locks::name.eq(name))
+                .filter(locks::lock_type.eq(LockKind::Semaphore))
+                .filter(tasks::status.eq_any(PENDING_STATES))
+                .select(locks::capacity)
+                .first::<Option<i32>>(self)
+                .optional()?;
+
+            Ok(capacity.map(|capacity| {
+                capacity
+                    .and_then(|capacity| u32::try_from(capacity).ok())
+                    .unwrap_or(1)
+            }))
+        }
+
         fn poison_all_locks_by_task(&mut self, task_id: Uuid) -> Result<usize, VickyError> {
             let affected = update(locks::table.filter(locks::task_id.eq(task_id)))
                 .set(locks::poisoned_by_task.eq(task_id))
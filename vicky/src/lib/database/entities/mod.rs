@@ -1,19 +1,32 @@
 pub mod lock;
+pub mod schedule;
 pub mod task;
+pub mod task_approval;
+pub mod task_edit;
+pub mod task_event;
 pub mod user;
 
 use crate::database::entities::lock::PoisonedLock;
 use crate::database::entities::lock::db_impl::LockDatabase;
-use crate::database::entities::task::TaskStatus;
+use crate::database::entities::schedule::db_impl::ScheduleDatabase;
 use crate::database::entities::task::db_impl::TaskDatabase;
+use crate::database::entities::task::{TaskRejection, TaskStatus, TimeoutSweep};
+use crate::database::entities::task_approval::db_impl::TaskApprovalDatabase;
+use crate::database::entities::task_approval::{ApprovalOutcome, TaskApproval};
+use crate::database::entities::task_edit::TaskEdit;
+use crate::database::entities::task_edit::db_impl::TaskEditDatabase;
+use crate::database::entities::task_event::db_impl::TaskEventDatabase;
+use crate::database::entities::task_event::{Actor, TaskEvent};
 use crate::database::entities::user::User;
 use crate::database::entities::user::db_impl::UserDatabase;
 use crate::errors::VickyError;
 use crate::query::FilterParams;
-use chrono::Utc;
+use crate::vicky::scheduler::SchedulerConfig;
+use chrono::{DateTime, TimeDelta, Utc};
 use delegate::delegate;
 pub use lock::{Lock, LockKind};
 use rocket_sync_db_pools::{ConnectionPool, database};
+pub use schedule::Schedule;
 pub use task::Task;
 use uuid::Uuid;
 
@@ -34,23 +47,56 @@ impl Database {
         to conn {
             pub async fn count_all_tasks<F: Into<FilterParams> + Send + 'static>(
                 &self,
-                task_status: Option<TaskStatus>,
+                #[as_ref] task_statuses: Vec<TaskStatus>,
                 filters: F,
             ) -> Result<i64, VickyError>;
             pub async fn get_all_tasks_filtered<F: Into<FilterParams> + Send + 'static>(
                 &self,
-                task_status: Option<TaskStatus>,
+                #[as_ref] task_statuses: Vec<TaskStatus>,
                 filters: F,
             ) -> Result<Vec<Task>, VickyError>;
             pub async fn get_all_tasks(&self) -> Result<Vec<Task>, VickyError>;
+            pub async fn get_pending_tasks(&self) -> Result<Vec<Task>, VickyError>;
             pub async fn get_task(&self, task_id: Uuid) -> Result<Option<Task>, VickyError>;
+            pub async fn get_task_by_idempotency_key(&self, #[as_ref] key: String) -> Result<Option<Task>, VickyError>;
+            pub async fn get_latest_attempt(&self, task_id: Uuid) -> Result<Option<Task>, VickyError>;
+            pub async fn claim_next_task(&self, #[as_ref] features: Vec<String>, #[as_ref] config: SchedulerConfig, #[as_ref] actor: Actor) -> Result<Option<Task>, VickyError>;
             pub async fn put_task(&self, task: Task) -> Result<usize, VickyError>;
             pub async fn update_task(&self, #[as_ref] task: Task) -> Result<usize, VickyError>;
-            pub async fn confirm_task(&self, uuid: Uuid) -> Result<usize, VickyError>;
+            pub async fn finish_task(&self, #[as_ref] task: Task, poison_locks_on_cancel: bool, #[as_ref] actor: Actor) -> Result<usize, VickyError>;
+            pub async fn confirm_task(&self, uuid: Uuid, #[as_ref] actor: Actor) -> Result<usize, VickyError>;
             pub async fn has_task(&self, task_id: Uuid) -> Result<bool, VickyError>;
             pub async fn has_running_task(&self, task_id: Uuid) -> Result<bool, VickyError>;
-            pub async fn perform_timeout_sweep(&self) -> Result<(usize, usize), VickyError>;
+            pub async fn perform_timeout_sweep(&self, default_max_runtime: TimeDelta) -> Result<TimeoutSweep, VickyError>;
             pub async fn timeout_task(&self, task_id: Uuid) -> Result<usize, VickyError>;
+            pub async fn request_task_cancellation(&self, task_id: Uuid, #[as_ref] actor: Actor) -> Result<usize, VickyError>;
+            pub async fn cancel_task(&self, task_id: Uuid, poison_locks: bool, #[as_ref] actor: Actor) -> Result<usize, VickyError>;
+            pub async fn reject_task(&self, task_id: Uuid, #[as_ref] rejection: TaskRejection, #[as_ref] actor: Actor) -> Result<usize, VickyError>;
+            pub async fn rerun_task(&self, #[as_ref] task: Task, clear_poison: bool, created_by: Option<Uuid>) -> Result<Option<Task>, VickyError>;
+        }
+
+        #[await(false)]
+        #[expr(self.run(move |conn| $).await)]
+        #[through(TaskApprovalDatabase)]
+        to conn {
+            pub async fn approve_task(&self, #[as_ref] task: Task, user_id: Uuid) -> Result<ApprovalOutcome, VickyError>;
+            pub async fn get_task_approvals(&self, task_id: Uuid) -> Result<Vec<TaskApproval>, VickyError>;
+        }
+
+        #[await(false)]
+        #[expr(self.run(move |conn| $).await)]
+        #[through(TaskEditDatabase)]
+        to conn {
+            pub async fn edit_task(&self, #[as_ref] task: Task, #[as_ref] edit: TaskEdit) -> Result<usize, VickyError>;
+            pub async fn get_task_edits(&self, task_id: Uuid) -> Result<Vec<TaskEdit>, VickyError>;
+        }
+
+        #[await(false)]
+        #[expr(self.run(move |conn| $).await)]
+        #[through(TaskEventDatabase)]
+        to conn {
+            pub async fn put_task_event(&self, #[as_ref] event: TaskEvent) -> Result<usize, VickyError>;
+            pub async fn get_task_events(&self, task_id: Uuid) -> Result<Vec<TaskEvent>, VickyError>;
         }
 
         #[await(false)]
@@ -60,9 +106,22 @@ impl Database {
             pub async fn get_poisoned_locks(&self) -> Result<Vec<Lock>, VickyError>;
             pub async fn get_poisoned_locks_with_tasks(&self) -> Result<Vec<PoisonedLock>, VickyError>;
             pub async fn get_active_locks(&self) -> Result<Vec<Lock>, VickyError>;
+            pub async fn get_semaphore_capacity(&self, #[as_ref] name: String) -> Result<Option<u32>, VickyError>;
             pub async fn unlock_lock(&self, #[as_ref] lock_uuid: Uuid) -> Result<usize, VickyError>;
         }
 
+        #[await(false)]
+        #[expr(self.run(move |conn| $).await)]
+        #[through(ScheduleDatabase)]
+        to conn {
+            pub async fn get_all_schedules(&self) -> Result<Vec<Schedule>, VickyError>;
+            pub async fn get_schedule(&self, schedule_id: Uuid) -> Result<Option<Schedule>, VickyError>;
+            pub async fn put_schedule(&self, #[as_ref] schedule: Schedule) -> Result<usize, VickyError>;
+            pub async fn update_schedule(&self, #[as_ref] schedule: Schedule) -> Result<usize, VickyError>;
+            pub async fn delete_schedule(&self, schedule_id: Uuid) -> Result<usize, VickyError>;
+            pub async fn run_due_schedules(&self, now: DateTime<Utc>) -> Result<(usize, usize), VickyError>;
+        }
+
         #[await(false)]
         #[expr(self.run(move |conn| $).await)]
         #[through(UserDatabase)]
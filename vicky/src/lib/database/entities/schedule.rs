@@ -0,0 +1,354 @@
+//! Schedules create a new task from their task spec whenever their cron expression is due.
+//!
+//! Cron expressions use the classic five fields (minute, hour, day of month, month, day of week)
+//! and may optionally be extended by a leading seconds and a trailing year field. They are
+//! evaluated in UTC.
+
+use crate::database::entities::lock::Lock;
+use crate::database::entities::schedule::db_impl::{DbSchedule, DbScheduleLock};
+use crate::database::entities::task::{FlakeRef, Task};
+use chrono::serde::{ts_seconds, ts_seconds_option};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: Uuid,
+    pub display_name: String,
+    pub cron: String,
+    pub flake_ref: FlakeRef,
+    pub locks: Vec<Lock>,
+    pub features: Vec<String>,
+    pub group: Option<String>,
+
+    #[serde(with = "ts_seconds")]
+    pub created_at: DateTime<Utc>,
+
+    /// `None` if the cron expression never matches again.
+    #[serde(with = "ts_seconds_option")]
+    pub next_run_at: Option<DateTime<Utc>>,
+
+    /// The task created by the latest run of this schedule.
+    pub last_task_id: Option<Uuid>,
+}
+
+/// Parses a cron expression, filling in a seconds field if only the classic five fields are given.
+pub fn parse_cron(expression: &str) -> Result<cron::Schedule, cron::error::Error> {
+    if expression.split_whitespace().count() == 5 {
+        cron::Schedule::from_str(&format!("0 {expression}"))
+    } else {
+        cron::Schedule::from_str(expression)
+    }
+}
+
+impl Schedule {
+    pub fn next_run_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        parse_cron(&self.cron).ok()?.after(&after).next()
+    }
+
+    /// Creates a new task from the task spec of this schedule.
+    /// Returns `None` if the locks of the spec conflict with each other.
+    pub fn instantiate(&self) -> Option<Task> {
+        Task::builder()
+            .display_name(self.display_name.clone())
+            .flake(self.flake_ref.flake.clone())
+            .flake_args(self.flake_ref.args.clone())
+            .locks(self.locks.clone())
+            .requires_features(self.features.clone())
+            .maybe_group(self.group.clone())
+            .build()
+            .ok()
+    }
+}
+
+impl AsRef<Schedule> for Schedule {
+    fn as_ref(&self) -> &Schedule {
+        self
+    }
+}
+
+impl From<(DbSchedule, Vec<DbScheduleLock>)> for Schedule {
+    fn from(value: (DbSchedule, Vec<DbScheduleLock>)) -> Self {
+        let (schedule, locks) = value;
+
+        Schedule {
+            id: schedule.id,
+            display_name: schedule.display_name,
+            cron: schedule.cron,
+            flake_ref: FlakeRef {
+                flake: schedule.flake_ref_uri,
+                args: schedule.flake_ref_args,
+            },
+            locks: locks.into_iter().map(Lock::from).collect(),
+            features: schedule.features,
+            group: schedule.group,
+            created_at: schedule.created_at,
+            next_run_at: schedule.next_run_at,
+            last_task_id: schedule.last_task_id,
+        }
+    }
+}
+
+pub mod db_impl {
+    use chrono::{DateTime, Utc};
+    use diesel::prelude::*;
+    use itertools::Itertools;
+    use serde::Serialize;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    use crate::database::entities::lock::{Lock, LockKind};
+    use crate::database::entities::schedule::Schedule;
+    use crate::database::entities::task::db_impl::{PENDING_STATES, TaskDatabase};
+    use crate::database::schema::{schedule_locks, schedules, tasks};
+    use crate::errors::VickyError;
+
+    #[derive(Insertable, Queryable, AsChangeset, Debug, Serialize)]
+    #[diesel(table_name = schedules)]
+    #[diesel(treat_none_as_null = true)]
+    pub struct DbSchedule {
+        pub id: Uuid,
+        pub display_name: String,
+        pub cron: String,
+        pub flake_ref_uri: String,
+        pub flake_ref_args: Vec<String>,
+        pub features: Vec<String>,
+        pub group: Option<String>,
+        pub created_at: DateTime<Utc>,
+        pub next_run_at: Option<DateTime<Utc>>,
+        pub last_task_id: Option<Uuid>,
+    }
+
+    #[derive(Queryable, Debug, Serialize)]
+    #[diesel(table_name = schedule_locks)]
+    pub struct DbScheduleLock {
+        pub id: Uuid,
+        pub schedule_id: Uuid,
+        pub name: String,
+        pub lock_type: LockKind,
+        pub capacity: Option<i32>,
+    }
+
+    #[derive(Insertable, Debug)]
+    #[diesel(table_name = schedule_locks)]
+    pub struct NewDbScheduleLock {
+        pub schedule_id: Uuid,
+        pub name: String,
+        pub lock_type: LockKind,
+        pub capacity: Option<i32>,
+    }
+
+    impl NewDbScheduleLock {
+        pub fn from_lock(lock: &Lock, schedule_id: Uuid) -> Self {
+            NewDbScheduleLock {
+                schedule_id,
+                name: lock.name.clone(),
+                lock_type: lock.kind,
+                capacity: lock
+                    .capacity
+                    .map(|capacity| i32::try_from(capacity).unwrap_or(i32::MAX)),
+            }
+        }
+    }
+
+    impl From<DbScheduleLock> for Lock {
+        fn from(lock: DbScheduleLock) -> Lock {
+            Lock {
+                name: lock.name,
+                kind: lock.lock_type,
+                poisoned_by: None,
+                capacity: lock
+                    .capacity
+                    .and_then(|capacity| u32::try_from(capacity).ok()),
+            }
+        }
+    }
+
+    impl From<&Schedule> for DbSchedule {
+        fn from(schedule: &Schedule) -> DbSchedule {
+            DbSchedule {
+                id: schedule.id,
+                display_name: schedule.display_name.clone(),
+                cron: schedule.cron.clone(),
+                flake_ref_uri: schedule.flake_ref.flake.clone(),
+                flake_ref_args: schedule.flake_ref.args.clone(),
+                features: schedule.features.clone(),
+                group: schedule.group.clone(),
+                created_at: schedule.created_at,
+                next_run_at: schedule.next_run_at,
+                last_task_id: schedule.last_task_id,
+            }
+        }
+    }
+
+    trait ScheduleRelations {
+        fn load_schedule_locks(
+            &mut self,
+            db_schedules: Vec<DbSchedule>,
+        ) -> Result<Vec<Schedule>, VickyError>;
+        fn insert_schedule_locks(&mut self, schedule: &Schedule) -> Result<usize, VickyError>;
+    }
+
+    impl ScheduleRelations for PgConnection {
+        fn load_schedule_locks(
+            &mut self,
+            db_schedules: Vec<DbSchedule>,
+        ) -> Result<Vec<Schedule>, VickyError> {
+            let schedule_ids: Vec<Uuid> = db_schedules.iter().map(|s| s.id).collect();
+
+            let mut lock_map: HashMap<_, Vec<DbScheduleLock>> = schedule_locks::table
+                .filter(schedule_locks::schedule_id.eq_any(&schedule_ids))
+                .load::<DbScheduleLock>(self)?
+                .into_iter()
+                .map(|db_lock| (db_lock.schedule_id, db_lock))
+                .into_group_map();
+
+            let schedules = db_schedules
+                .into_iter()
+                .map(|s| {
+                    let locks = lock_map.remove(&s.id).unwrap_or_default();
+                    (s, locks).into()
+                })
+                .collect();
+
+            Ok(schedules)
+        }
+
+        fn insert_schedule_locks(&mut self, schedule: &Schedule) -> Result<usize, VickyError> {
+            let db_locks: Vec<NewDbScheduleLock> = schedule
+                .locks
+                .iter()
+                .map(|lock| NewDbScheduleLock::from_lock(lock, schedule.id))
+                .collect();
+
+            Ok(diesel::insert_into(schedule_locks::table)
+                .values(db_locks)
+                .execute(self)?)
+        }
+    }
+
+    pub trait ScheduleDatabase {
+        fn get_all_schedules(&mut self) -> Result<Vec<Schedule>, VickyError>;
+        fn get_schedule(&mut self, schedule_id: Uuid) -> Result<Option<Schedule>, VickyError>;
+        fn put_schedule(&mut self, schedule: &Schedule) -> Result<usize, VickyError>;
+        fn update_schedule(&mut self, schedule: &Schedule) -> Result<usize, VickyError>;
+        fn delete_schedule(&mut self, schedule_id: Uuid) -> Result<usize, VickyError>;
+        fn run_due_schedules(&mut self, now: DateTime<Utc>) -> Result<(usize, usize), VickyError>;
+    }
+
+    impl ScheduleDatabase for PgConnection {
+        fn get_all_schedules(&mut self) -> Result<Vec<Schedule>, VickyError> {
+            let db_schedules = schedules::table
+                .order(schedules::created_at.desc())
+                .load::<DbSchedule>(self)?;
+
+            self.load_schedule_locks(db_schedules)
+        }
+
+        fn get_schedule(&mut self, schedule_id: Uuid) -> Result<Option<Schedule>, VickyError> {
+            let db_schedule = schedules::table
+                .filter(schedules::id.eq(schedule_id))
+                .first::<DbSchedule>(self)
+                .optional()?;
+
+            let Some(db_schedule) = db_schedule else {
+                return Ok(None);
+            };
+
+            Ok(self.load_schedule_locks(vec![db_schedule])?.pop())
+        }
+
+        fn put_schedule(&mut self, schedule: &Schedule) -> Result<usize, VickyError> {
+            self.transaction(|conn| {
+                let affected = diesel::insert_into(schedules::table)
+                    .values(DbSchedule::from(schedule))
+                    .execute(conn)?;
+                conn.insert_schedule_locks(schedule)?;
+
+                Ok(affected)
+            })
+        }
+
+        fn update_schedule(&mut self, schedule: &Schedule) -> Result<usize, VickyError> {
+            self.transaction(|conn| {
+                let affected =
+                    diesel::update(schedules::table.filter(schedules::id.eq(schedule.id)))
+                        .set(DbSchedule::from(schedule))
+                        .execute(conn)?;
+
+                diesel::delete(
+                    schedule_locks::table.filter(schedule_locks::schedule_id.eq(schedule.id)),
+                )
+                .execute(conn)?;
+                conn.insert_schedule_locks(schedule)?;
+
+                Ok(affected)
+            })
+        }
+
+        fn delete_schedule(&mut self, schedule_id: Uuid) -> Result<usize, VickyError> {
+            Ok(
+                diesel::delete(schedules::table.filter(schedules::id.eq(schedule_id)))
+                    .execute(self)?,
+            )
+        }
+
+        /// Creates a task for every due schedule, unless the task of its previous run is still
+        /// pending. Either way, the schedule moves on to its next run.
+        /// Schedules are locked row by row, so concurrent instances never run a schedule twice.
+        ///
+        /// Returns the amount of started and skipped runs.
+        fn run_due_schedules(&mut self, now: DateTime<Utc>) -> Result<(usize, usize), VickyError> {
+            self.transaction(|conn| {
+                let due_schedules = schedules::table
+                    .filter(schedules::next_run_at.le(now))
+                    .for_update()
+                    .skip_locked()
+                    .load::<DbSchedule>(conn)?;
+                let due_schedules = conn.load_schedule_locks(due_schedules)?;
+
+                let mut started = 0;
+                let mut skipped = 0;
+
+                for mut schedule in due_schedules {
+                    let previous_pending = match schedule.last_task_id {
+                        Some(task_id) => diesel::select(diesel::dsl::exists(
+                            tasks::table
+                                .filter(tasks::id.eq(task_id))
+                                .filter(tasks::status.eq_any(PENDING_STATES)),
+                        ))
+                        .get_result::<bool>(conn)?,
+                        None => false,
+                    };
+
+                    let task = match previous_pending {
+                        true => None,
+                        false => schedule.instantiate(),
+                    };
+
+                    match task {
+                        Some(task) => {
+                            schedule.last_task_id = Some(task.id);
+                            conn.put_task(task)?;
+                            started += 1;
+                        }
+                        None => skipped += 1,
+                    }
+
+                    schedule.next_run_at = schedule.next_run_after(now);
+
+                    diesel::update(schedules::table.filter(schedules::id.eq(schedule.id)))
+                        .set((
+                            schedules::next_run_at.eq(schedule.next_run_at),
+                            schedules::last_task_id.eq(schedule.last_task_id),
+                        ))
+                        .execute(conn)?;
+                }
+
+                Ok((started, skipped))
+            })
+        }
+    }
+}
@@ -1,16 +1,18 @@
 use crate::database::entities::lock::Lock;
 use crate::database::entities::lock::db_impl::DbLock;
-use crate::database::entities::task::db_impl::DbTask;
+use crate::database::entities::task::db_impl::{DbTask, DbTaskDependency, DbTaskLabel};
 use bon::Builder;
 use chrono::serde::ts_seconds;
 use chrono::serde::ts_seconds_option;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 use diesel::{AsExpression, FromSqlRow};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use uuid::Uuid;
 
 pub const HEARTBEAT_TIMEOUT_SEC: i64 = 60;
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
 #[allow(unused)]
 pub const EXPECTED_HEARTBEAT_INTERVAL_SEC: i64 = 15;
 
@@ -21,6 +23,72 @@ pub enum TaskResult {
     Error,
     Timeout,
     Cancel,
+    /// Set by the server when a task the task depends on failed.
+    #[value(skip)]
+    DependencyFailed,
+    /// A newer task with the same `supersede_key` was created before this one ran.
+    #[value(skip)]
+    Superseded,
+}
+
+/// Tasks that finish with one of the `retry_on` results are retried as a new, linked task, until
+/// `max_attempts` attempts were made. The n-th retry waits `backoff_sec * 2^(n-1)` seconds.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    #[serde(default)]
+    pub backoff_sec: u32,
+    #[serde(default = "RetryPolicy::default_retry_on")]
+    pub retry_on: Vec<TaskResult>,
+}
+
+impl RetryPolicy {
+    fn default_retry_on() -> Vec<TaskResult> {
+        vec![TaskResult::Error, TaskResult::Timeout]
+    }
+
+    /// Only failures of the task itself can be retried.
+    pub fn is_valid(&self) -> bool {
+        self.max_attempts >= 1
+            && self
+                .retry_on
+                .iter()
+                .all(|result| matches!(result, TaskResult::Error | TaskResult::Timeout))
+    }
+
+    pub fn retries(&self, result: TaskResult, attempt: i32) -> bool {
+        self.retry_on.contains(&result) && i64::from(attempt) < i64::from(self.max_attempts)
+    }
+
+    /// The delay before the attempt following `attempt`.
+    pub fn backoff(&self, attempt: i32) -> TimeDelta {
+        let exponent = u32::try_from(attempt.saturating_sub(1)).unwrap_or(0);
+        let factor = 2u32.saturating_pow(exponent);
+        TimeDelta::seconds(i64::from(self.backoff_sec.saturating_mul(factor)))
+    }
+}
+
+/// Response to a heartbeat of a running task.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heartbeat {
+    /// The fairy should kill the task and finish it as cancelled.
+    pub cancel_requested: bool,
+}
+
+/// Outcome of a single timeout sweep.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TimeoutSweep {
+    /// Tasks that timed out after not sending a heartbeat for `HEARTBEAT_TIMEOUT_SEC` seconds.
+    pub heartbeat_timeouts: usize,
+    /// Tasks that timed out after running longer than their maximum runtime.
+    pub runtime_timeouts: usize,
+    pub poisoned_locks: usize,
+}
+
+impl TimeoutSweep {
+    pub fn is_empty(&self) -> bool {
+        self.heartbeat_timeouts == 0 && self.runtime_timeouts == 0
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, FromSqlRow, AsExpression)]
@@ -35,6 +103,24 @@ pub enum TaskStatus {
 
 type FlakeURI = String;
 
+/// Why a task awaiting confirmation was refused.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TaskRejection {
+    pub comment: String,
+
+    #[serde(with = "ts_seconds")]
+    pub rejected_at: DateTime<Utc>,
+
+    /// The user who rejected the task, `None` for machines.
+    pub rejected_by: Option<Uuid>,
+}
+
+impl AsRef<TaskRejection> for TaskRejection {
+    fn as_ref(&self) -> &TaskRejection {
+        self
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct FlakeRef {
     pub flake: FlakeURI,
@@ -64,6 +150,14 @@ pub struct Task {
     #[builder(field)]
     pub features: Vec<String>,
 
+    #[builder(field)]
+    pub depends_on: Vec<Uuid>,
+
+    /// Key/value pairs to classify tasks by, e.g. `env=prod`.
+    #[builder(field)]
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+
     #[builder(default = Uuid::new_v4())]
     pub id: Uuid,
 
@@ -74,7 +168,7 @@ pub struct Task {
     pub status: TaskStatus,
 
     #[serde(with = "ts_seconds")]
-    #[builder(skip = Utc::now())]
+    #[builder(default = Utc::now())]
     pub created_at: DateTime<Utc>,
 
     #[serde(with = "ts_seconds_option")]
@@ -87,18 +181,153 @@ pub struct Task {
     pub last_heartbeat: Option<DateTime<Utc>>,
 
     pub group: Option<String>,
+
+    /// Tasks with a higher priority are scheduled first, ties are broken by age.
+    #[builder(default = 0)]
+    pub priority: i32,
+
+    /// Deferred tasks aren't scheduled before this point in time.
+    #[serde(default, with = "ts_seconds_option")]
+    pub not_before: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub retry_policy: Option<RetryPolicy>,
+
+    /// Starts at 1 and counts up for every retry of the original task.
+    #[builder(default = 1)]
+    #[serde(default = "Task::first_attempt")]
+    pub attempt: i32,
+
+    /// The previous attempt, if this task is a retry.
+    #[serde(default)]
+    pub retry_of: Option<Uuid>,
+
+    /// Seconds the task may run after being claimed, regardless of heartbeats.
+    /// Falls back to the server-wide default if not set.
+    #[serde(default)]
+    pub max_runtime: Option<u32>,
+
+    /// Set once the cancellation of the running task was requested.
+    /// The fairy learns about it from the response to its next heartbeat.
+    #[serde(default, with = "ts_seconds_option")]
+    pub cancel_requested_at: Option<DateTime<Utc>>,
+
+    /// Whether the task had to be confirmed by a user before it could run.
+    #[builder(default = false)]
+    #[serde(default)]
+    pub needs_confirmation: bool,
+
+    /// The task this one was re-run from.
+    #[serde(default)]
+    pub rerun_of: Option<Uuid>,
+
+    /// Distinct users, other than the creator, that have to approve the task before it runs.
+    /// Machines can't confirm tasks that require approvals.
+    #[builder(default = 0)]
+    #[serde(default)]
+    pub required_approvals: u32,
+
+    /// The user who created the task, `None` for machines.
+    #[serde(default)]
+    pub created_by: Option<Uuid>,
+
+    /// Set if the task was rejected instead of being confirmed.
+    #[serde(default)]
+    pub rejection: Option<TaskRejection>,
+
+    /// Chosen by the submitter, a repeated submission with the same key returns this task.
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+
+    /// Creating a task supersedes the older tasks with the same key that didn't start yet.
+    /// Retries and re-runs don't inherit it.
+    #[serde(default)]
+    pub supersede_key: Option<String>,
 }
 
 impl Task {
+    fn first_attempt() -> i32 {
+        1
+    }
+
     pub fn finish(&mut self, result: TaskResult) {
         self.status = TaskStatus::Finished(result);
         self.finished_at = Some(Utc::now());
 
-        if result == TaskResult::Error {
+        if result == TaskResult::Error && !self.will_retry(result) {
             self.locks.iter_mut().for_each(|lock| lock.poison(&self.id));
         }
     }
 
+    pub fn will_retry(&self, result: TaskResult) -> bool {
+        self.retry_policy
+            .as_ref()
+            .is_some_and(|policy| policy.retries(result, self.attempt))
+    }
+
+    /// Creates the retry of this task after it finished with `result`, if its retry policy allows it.
+    /// The retry is deferred by the backoff of the policy.
+    pub fn next_attempt(&self, result: TaskResult) -> Option<Task> {
+        if !self.will_retry(result) {
+            return None;
+        }
+        let policy = self.retry_policy.as_ref()?;
+
+        let mut locks = self.locks.clone();
+        locks.iter_mut().for_each(|lock| lock.clear_poison());
+
+        Task::builder()
+            .display_name(self.display_name.clone())
+            .flake(self.flake_ref.flake.clone())
+            .flake_args(self.flake_ref.args.clone())
+            .locks(locks)
+            .requires_features(self.features.clone())
+            .dependencies(self.depends_on.clone())
+            .labels(self.labels.clone())
+            .maybe_group(self.group.clone())
+            .priority(self.priority)
+            .not_before(Utc::now() + policy.backoff(self.attempt))
+            .retry_policy(policy.clone())
+            .attempt(self.attempt + 1)
+            .retry_of(self.id)
+            .maybe_max_runtime(self.max_runtime)
+            // the confirmation carries over to the retry, but a rerun of it asks again
+            .needs_confirmation(self.needs_confirmation)
+            .required_approvals(self.required_approvals)
+            .maybe_created_by(self.created_by)
+            .build()
+            .ok()
+    }
+
+    /// Creates a fresh task from the spec of this one, e.g. after the cause of its failure was fixed.
+    pub fn rerun(&self) -> Option<Task> {
+        let mut locks = self.locks.clone();
+        locks.iter_mut().for_each(|lock| lock.clear_poison());
+
+        let status = match self.needs_confirmation {
+            true => TaskStatus::NeedsUserValidation,
+            false => TaskStatus::New,
+        };
+
+        Task::builder()
+            .status(status)
+            .display_name(self.display_name.clone())
+            .flake(self.flake_ref.flake.clone())
+            .flake_args(self.flake_ref.args.clone())
+            .locks(locks)
+            .requires_features(self.features.clone())
+            .labels(self.labels.clone())
+            .maybe_group(self.group.clone())
+            .priority(self.priority)
+            .maybe_retry_policy(self.retry_policy.clone())
+            .maybe_max_runtime(self.max_runtime)
+            .needs_confirmation(self.needs_confirmation)
+            .required_approvals(self.required_approvals)
+            .rerun_of(self.id)
+            .build()
+            .ok()
+    }
+
     pub fn poison_locks(&mut self) {
         self.locks.iter_mut().for_each(|lock| lock.poison(&self.id));
     }
@@ -142,6 +371,11 @@ impl<T: task_builder::State> TaskBuilder<T> {
         self
     }
 
+    pub fn semaphore_lock<S: Into<String>>(mut self, name: S, capacity: u32) -> Self {
+        self.locks.push(Lock::semaphore(name, capacity));
+        self
+    }
+
     pub fn locks(mut self, locks: Vec<Lock>) -> Self {
         self.locks = locks;
         self
@@ -172,6 +406,26 @@ impl<T: task_builder::State> TaskBuilder<T> {
         self
     }
 
+    pub fn depends_on(mut self, task_id: Uuid) -> Self {
+        self.depends_on.push(task_id);
+        self
+    }
+
+    pub fn dependencies(mut self, task_ids: Vec<Uuid>) -> Self {
+        self.depends_on = task_ids;
+        self
+    }
+
+    pub fn label<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn labels(mut self, labels: BTreeMap<String, String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
     pub fn check_lock_conflict(&self) -> bool {
         self.locks
             .iter()
@@ -207,6 +461,13 @@ impl<T: task_builder::IsComplete> TaskBuilder<T> {
 impl From<(DbTask, Vec<DbLock>)> for Task {
     fn from(value: (DbTask, Vec<DbLock>)) -> Self {
         let (task, locks) = value;
+        Task::from((task, locks, vec![], vec![]))
+    }
+}
+
+impl From<(DbTask, Vec<DbLock>, Vec<DbTaskDependency>, Vec<DbTaskLabel>)> for Task {
+    fn from(value: (DbTask, Vec<DbLock>, Vec<DbTaskDependency>, Vec<DbTaskLabel>)) -> Self {
+        let (task, locks, dependencies, labels) = value;
         Task {
             id: task.id,
             display_name: task.display_name,
@@ -217,11 +478,50 @@ impl From<(DbTask, Vec<DbLock>)> for Task {
                 args: task.flake_ref_args,
             },
             features: task.features,
+            depends_on: dependencies.into_iter().map(|d| d.depends_on).collect(),
+            labels: labels.into_iter().map(|l| (l.key, l.value)).collect(),
             created_at: task.created_at,
             claimed_at: task.claimed_at,
             finished_at: task.finished_at,
             last_heartbeat: task.last_heartbeat,
             group: task.group,
+            priority: task.priority,
+            not_before: task.not_before,
+            retry_policy: task.retry_max_attempts.map(|max_attempts| RetryPolicy {
+                max_attempts: u32::try_from(max_attempts).unwrap_or(1),
+                backoff_sec: task
+                    .retry_backoff_sec
+                    .and_then(|backoff_sec| u32::try_from(backoff_sec).ok())
+                    .unwrap_or(0),
+                retry_on: task
+                    .retry_on
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|status| match TaskStatus::try_from(status.as_str()) {
+                        Ok(TaskStatus::Finished(result)) => Some(result),
+                        _ => None,
+                    })
+                    .collect(),
+            }),
+            attempt: task.attempt,
+            retry_of: task.retry_of,
+            max_runtime: task
+                .max_runtime
+                .and_then(|max_runtime| u32::try_from(max_runtime).ok()),
+            cancel_requested_at: task.cancel_requested_at,
+            needs_confirmation: task.needs_confirmation,
+            rerun_of: task.rerun_of,
+            required_approvals: u32::try_from(task.required_approvals).unwrap_or_default(),
+            created_by: task.created_by,
+            rejection: task.rejection_comment.zip(task.rejected_at).map(
+                |(comment, rejected_at)| TaskRejection {
+                    comment,
+                    rejected_at,
+                    rejected_by: task.rejected_by,
+                },
+            ),
+            idempotency_key: task.idempotency_key,
+            supersede_key: task.supersede_key,
         }
     }
 }
@@ -234,12 +534,26 @@ impl TaskStatus {
             | TaskStatus::New
             | TaskStatus::Running
             | TaskStatus::Finished(TaskResult::Success) => false,
-            TaskStatus::Finished(TaskResult::Error | TaskResult::Timeout | TaskResult::Cancel) => {
-                true
-            }
+            TaskStatus::Finished(
+                TaskResult::Error
+                | TaskResult::Timeout
+                | TaskResult::Cancel
+                | TaskResult::DependencyFailed
+                | TaskResult::Superseded,
+            ) => true,
         }
     }
 
+    /// Whether the locks of a task in this state get poisoned. Tasks that failed because of a
+    /// dependency or were superseded never ran, so they didn't touch anything their locks protect.
+    pub fn poisons_locks(self) -> bool {
+        self.is_failed()
+            && !matches!(
+                self,
+                TaskStatus::Finished(TaskResult::DependencyFailed | TaskResult::Superseded)
+            )
+    }
+
     pub fn is_finished(self) -> bool {
         matches!(self, TaskStatus::Finished(_))
     }
@@ -256,22 +570,32 @@ impl TaskStatus {
 // this was on purpose because these macro-generated entity types
 // mess up the whole namespace and HAVE to be scoped
 pub mod db_impl {
-    use crate::database::entities::task::{HEARTBEAT_TIMEOUT_SEC, Task, TaskResult, TaskStatus};
+    use crate::database::entities::task::{
+        HEARTBEAT_TIMEOUT_SEC, Task, TaskRejection, TaskResult, TaskStatus, TimeoutSweep,
+    };
+    use crate::database::entities::task_event::db_impl::TaskEventDatabase;
+    use crate::database::entities::task_event::{Actor, TaskEvent};
     use crate::errors::VickyError;
-    use crate::query::FilterParams;
-    use chrono::{DateTime, NaiveDateTime, Utc};
+    use crate::query::{FilterParams, SortOrder, TaskSort, like_pattern};
+    use crate::vicky::scheduler::{Scheduler, SchedulerConfig};
+    use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
 
     // these here are evil >:(
-    use crate::database::entities::lock::db_impl::{DbLock, NewDbLock};
+    use crate::database::entities::lock::db_impl::{DbLock, LockDatabase, NewDbLock};
     use crate::database::schema::locks;
+    use crate::database::schema::task_dependencies;
+    use crate::database::schema::task_labels;
     use crate::database::schema::tasks;
     use diesel::deserialize::FromSql;
     use diesel::dsl::now;
-    use diesel::pg::PgValue;
+    use diesel::pg::{Pg, PgValue};
     use diesel::serialize::{IsNull, Output, ToSql};
+    use diesel::sql_types::BigInt;
     use diesel::{
-        AsChangeset, BoolExpressionMethods, Connection, ExpressionMethods, Insertable,
-        NullableExpressionMethods, QueryDsl, QueryId, Queryable, RunQueryDsl, SqlType,
+        AsChangeset, BoolExpressionMethods, Connection, ExpressionMethods, Insertable, JoinOnDsl,
+        NullableExpressionMethods, OptionalExtension, PgSortExpressionMethods,
+        PgTextExpressionMethods, QueryDsl, QueryId, Queryable, RunQueryDsl, SqlType,
+        TextExpressionMethods,
     };
     use itertools::Itertools;
     use serde::Serialize;
@@ -316,8 +640,50 @@ pub mod db_impl {
         pub finished_at: Option<DateTime<Utc>>,
         pub last_heartbeat: Option<DateTime<Utc>>,
         pub group: Option<String>,
+        pub priority: i32,
+        pub not_before: Option<DateTime<Utc>>,
+        pub attempt: i32,
+        pub retry_of: Option<Uuid>,
+        pub retry_max_attempts: Option<i32>,
+        pub retry_backoff_sec: Option<i32>,
+        pub retry_on: Option<Vec<String>>,
+        pub max_runtime: Option<i32>,
+        pub cancel_requested_at: Option<DateTime<Utc>>,
+        pub needs_confirmation: bool,
+        pub rerun_of: Option<Uuid>,
+        pub required_approvals: i32,
+        pub created_by: Option<Uuid>,
+        pub rejection_comment: Option<String>,
+        pub rejected_at: Option<DateTime<Utc>>,
+        pub rejected_by: Option<Uuid>,
+        pub idempotency_key: Option<String>,
+        pub supersede_key: Option<String>,
+    }
+
+    #[derive(Insertable, Queryable, Debug, Serialize)]
+    #[diesel(table_name = task_dependencies)]
+    pub struct DbTaskDependency {
+        pub task_id: Uuid,
+        pub depends_on: Uuid,
+    }
+
+    #[derive(Insertable, Queryable, Debug, Serialize)]
+    #[diesel(table_name = task_labels)]
+    pub struct DbTaskLabel {
+        pub task_id: Uuid,
+        pub key: String,
+        pub value: String,
     }
 
+    /// Key of the postgres advisory lock that serializes all task claims
+    const CLAIM_ADVISORY_LOCK_KEY: i64 = 0x0076_6963_6b79; // "vicky"
+
+    pub const PENDING_STATES: [TaskStatus; 3] = [
+        TaskStatus::NeedsUserValidation,
+        TaskStatus::New,
+        TaskStatus::Running,
+    ];
+
     pub const STATE_NEEDS_USER_VALIDATION_STR: &str = "NEEDS_USER_VALIDATION";
     pub const STATE_NEW_STR: &str = "NEW";
     pub const STATE_RUNNING_STR: &str = "RUNNING";
@@ -325,6 +691,8 @@ pub mod db_impl {
     pub const STATE_FINISHED_ERROR_STR: &str = "FINISHED::ERROR";
     pub const STATE_FINISHED_TIMEOUT_STR: &str = "FINISHED::TIMEOUT";
     pub const STATE_FINISHED_CANCEL_STR: &str = "FINISHED::CANCEL";
+    pub const STATE_FINISHED_DEPENDENCY_FAILED_STR: &str = "FINISHED::DEPENDENCY_FAILED";
+    pub const STATE_FINISHED_SUPERSEDED_STR: &str = "FINISHED::SUPERSEDED";
 
     impl Display for TaskStatus {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -337,6 +705,8 @@ pub mod db_impl {
                     TaskResult::Error => STATE_FINISHED_ERROR_STR,
                     TaskResult::Timeout => STATE_FINISHED_TIMEOUT_STR,
                     TaskResult::Cancel => STATE_FINISHED_CANCEL_STR,
+                    TaskResult::DependencyFailed => STATE_FINISHED_DEPENDENCY_FAILED_STR,
+                    TaskResult::Superseded => STATE_FINISHED_SUPERSEDED_STR,
                 },
             };
             write!(f, "{str}")
@@ -355,6 +725,10 @@ pub mod db_impl {
                 STATE_FINISHED_ERROR_STR => Ok(TaskStatus::Finished(TaskResult::Error)),
                 STATE_FINISHED_TIMEOUT_STR => Ok(TaskStatus::Finished(TaskResult::Timeout)),
                 STATE_FINISHED_CANCEL_STR => Ok(TaskStatus::Finished(TaskResult::Cancel)),
+                STATE_FINISHED_DEPENDENCY_FAILED_STR => {
+                    Ok(TaskStatus::Finished(TaskResult::DependencyFailed))
+                }
+                STATE_FINISHED_SUPERSEDED_STR => Ok(TaskStatus::Finished(TaskResult::Superseded)),
                 _ => Err("Could not deserialize to TaskStatus"),
             }
         }
@@ -374,23 +748,221 @@ pub mod db_impl {
                 finished_at: task.finished_at,
                 last_heartbeat: task.last_heartbeat,
                 group: task.group,
+                priority: task.priority,
+                not_before: task.not_before,
+                attempt: task.attempt,
+                retry_of: task.retry_of,
+                retry_max_attempts: task
+                    .retry_policy
+                    .as_ref()
+                    .map(|policy| i32::try_from(policy.max_attempts).unwrap_or(i32::MAX)),
+                retry_backoff_sec: task
+                    .retry_policy
+                    .as_ref()
+                    .map(|policy| i32::try_from(policy.backoff_sec).unwrap_or(i32::MAX)),
+                retry_on: task.retry_policy.map(|policy| {
+                    policy
+                        .retry_on
+                        .into_iter()
+                        .map(|result| TaskStatus::Finished(result).to_string())
+                        .collect()
+                }),
+                max_runtime: task
+                    .max_runtime
+                    .map(|max_runtime| i32::try_from(max_runtime).unwrap_or(i32::MAX)),
+                cancel_requested_at: task.cancel_requested_at,
+                needs_confirmation: task.needs_confirmation,
+                rerun_of: task.rerun_of,
+                required_approvals: i32::try_from(task.required_approvals).unwrap_or(i32::MAX),
+                created_by: task.created_by,
+                rejection_comment: task
+                    .rejection
+                    .as_ref()
+                    .map(|rejection| rejection.comment.clone()),
+                rejected_at: task
+                    .rejection
+                    .as_ref()
+                    .map(|rejection| rejection.rejected_at),
+                rejected_by: task
+                    .rejection
+                    .as_ref()
+                    .and_then(|rejection| rejection.rejected_by),
+                idempotency_key: task.idempotency_key,
+                supersede_key: task.supersede_key,
+            }
+        }
+    }
+
+    trait TaskRelations {
+        fn load_task_relations(&mut self, db_tasks: Vec<DbTask>) -> Result<Vec<Task>, VickyError>;
+    }
+
+    impl TaskRelations for diesel::pg::PgConnection {
+        fn load_task_relations(&mut self, db_tasks: Vec<DbTask>) -> Result<Vec<Task>, VickyError> {
+            let task_ids: Vec<Uuid> = db_tasks.iter().map(|t| t.id).collect();
+
+            // prefetching the locks here, so we don't run into the N+1 Query Problem and distribute them
+            let task_locks = locks::table
+                .filter(locks::task_id.eq_any(&task_ids))
+                .load::<DbLock>(self)?;
+
+            let mut lock_map: HashMap<_, Vec<DbLock>> = task_locks
+                .into_iter()
+                .map(|db_lock| (db_lock.task_id, db_lock))
+                .into_group_map();
+
+            let task_dependencies = task_dependencies::table
+                .filter(task_dependencies::task_id.eq_any(&task_ids))
+                .load::<DbTaskDependency>(self)?;
+
+            let mut dependency_map: HashMap<_, Vec<DbTaskDependency>> = task_dependencies
+                .into_iter()
+                .map(|db_dependency| (db_dependency.task_id, db_dependency))
+                .into_group_map();
+
+            let mut label_map: HashMap<_, Vec<DbTaskLabel>> = task_labels::table
+                .filter(task_labels::task_id.eq_any(&task_ids))
+                .load::<DbTaskLabel>(self)?
+                .into_iter()
+                .map(|db_label| (db_label.task_id, db_label))
+                .into_group_map();
+
+            let real_tasks: Vec<Task> = db_tasks
+                .into_iter()
+                .map(|t| {
+                    let real_locks = lock_map.remove(&t.id).unwrap_or_default();
+                    let dependencies = dependency_map.remove(&t.id).unwrap_or_default();
+                    let labels = label_map.remove(&t.id).unwrap_or_default();
+
+                    (t, real_locks, dependencies, labels).into()
+                })
+                .collect();
+
+            Ok(real_tasks)
+        }
+    }
+
+    /// Applies the statuses and filters shared by listing and counting tasks.
+    /// An empty list of statuses matches tasks of any status.
+    fn filter_tasks(
+        task_statuses: &[TaskStatus],
+        filters: &FilterParams,
+    ) -> tasks::BoxedQuery<'static, Pg> {
+        let mut query = tasks::table.into_boxed();
+
+        if !task_statuses.is_empty() {
+            query = query.filter(tasks::status.eq_any(task_statuses.to_vec()))
+        }
+
+        if let Some(group) = &filters.group {
+            query = query.filter(tasks::group.eq(group.clone()))
+        }
+
+        for (key, value) in filters.label_selectors() {
+            let with_key = task_labels::table.filter(task_labels::key.eq(key.to_string()));
+            query = match value {
+                Some(value) => query.filter(
+                    tasks::id.eq_any(
+                        with_key
+                            .filter(task_labels::value.eq(value.to_string()))
+                            .select(task_labels::task_id),
+                    ),
+                ),
+                None => query.filter(tasks::id.eq_any(with_key.select(task_labels::task_id))),
+            };
+        }
+
+        if let Some(after) = filters.created_after.and_then(from_timestamp) {
+            query = query.filter(tasks::created_at.ge(after))
+        }
+        if let Some(before) = filters.created_before.and_then(from_timestamp) {
+            query = query.filter(tasks::created_at.lt(before))
+        }
+        if let Some(after) = filters.finished_after.and_then(from_timestamp) {
+            query = query.filter(tasks::finished_at.ge(after))
+        }
+        if let Some(before) = filters.finished_before.and_then(from_timestamp) {
+            query = query.filter(tasks::finished_at.lt(before))
+        }
+
+        if let Some(lock) = &filters.lock {
+            query = query.filter(
+                tasks::id.eq_any(
+                    locks::table
+                        .filter(locks::name.eq(lock.clone()))
+                        .select(locks::task_id),
+                ),
+            )
+        }
+
+        if let Some(flake) = &filters.flake {
+            query = query.filter(tasks::flake_ref_uri.like(like_pattern(flake)))
+        }
+
+        if let Some(search) = &filters.search {
+            query = query.filter(tasks::display_name.ilike(like_pattern(search)))
+        }
+
+        query
+    }
+
+    fn from_timestamp(secs: i64) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(secs, 0)
+    }
+
+    /// Sorts by the requested column, newest first by default. The id keeps the order stable.
+    fn sort_tasks(
+        query: tasks::BoxedQuery<'static, Pg>,
+        filters: &FilterParams,
+    ) -> tasks::BoxedQuery<'static, Pg> {
+        let sort = filters.sort.unwrap_or_default();
+        let order = filters.order.unwrap_or_default();
+
+        let query = match (sort, order) {
+            (TaskSort::CreatedAt, SortOrder::Asc) => query.order(tasks::created_at.asc()),
+            (TaskSort::CreatedAt, SortOrder::Desc) => query.order(tasks::created_at.desc()),
+            (TaskSort::FinishedAt, SortOrder::Asc) => {
+                query.order(tasks::finished_at.asc().nulls_last())
+            }
+            (TaskSort::FinishedAt, SortOrder::Desc) => {
+                query.order(tasks::finished_at.desc().nulls_last())
             }
+            (TaskSort::Priority, SortOrder::Asc) => query.order(tasks::priority.asc()),
+            (TaskSort::Priority, SortOrder::Desc) => query.order(tasks::priority.desc()),
+            (TaskSort::DisplayName, SortOrder::Asc) => query.order(tasks::display_name.asc()),
+            (TaskSort::DisplayName, SortOrder::Desc) => query.order(tasks::display_name.desc()),
+        };
+
+        match order {
+            SortOrder::Asc => query.then_order_by(tasks::id.asc()),
+            SortOrder::Desc => query.then_order_by(tasks::id.desc()),
         }
     }
 
     pub trait TaskDatabase {
         fn count_all_tasks<F: Into<FilterParams>>(
             &mut self,
-            task_status: Option<TaskStatus>,
+            task_statuses: &[TaskStatus],
             filters: F,
         ) -> Result<i64, VickyError>;
         fn get_all_tasks_filtered<F: Into<FilterParams>>(
             &mut self,
-            task_status: Option<TaskStatus>,
+            task_statuses: &[TaskStatus],
             filters: F,
         ) -> Result<Vec<Task>, VickyError>;
         fn get_all_tasks(&mut self) -> Result<Vec<Task>, VickyError>;
+        /// Loads all tasks the scheduler has to consider: pending tasks and the tasks they depend on
+        fn get_pending_tasks(&mut self) -> Result<Vec<Task>, VickyError>;
         fn get_task(&mut self, task_id: Uuid) -> Result<Option<Task>, VickyError>;
+        fn get_task_by_idempotency_key(&mut self, key: &str) -> Result<Option<Task>, VickyError>;
+        /// Follows the retries of a task to its latest attempt
+        fn get_latest_attempt(&mut self, task_id: Uuid) -> Result<Option<Task>, VickyError>;
+        fn claim_next_task(
+            &mut self,
+            features: &[String],
+            config: &SchedulerConfig,
+            actor: &Actor,
+        ) -> Result<Option<Task>, VickyError>;
         fn put_task(&mut self, task: Task) -> Result<usize, VickyError>;
         fn register_task_heartbeat(
             &mut self,
@@ -398,46 +970,131 @@ pub mod db_impl {
             heartbeat: NaiveDateTime,
         ) -> Result<usize, VickyError>;
         fn timeout_task(&mut self, task_id: Uuid) -> Result<usize, VickyError>;
-        fn perform_timeout_sweep(&mut self) -> Result<(usize, usize), VickyError>;
+        fn request_task_cancellation(
+            &mut self,
+            task_id: Uuid,
+            actor: &Actor,
+        ) -> Result<usize, VickyError>;
+        fn cancel_task(
+            &mut self,
+            task_id: Uuid,
+            poison_locks: bool,
+            actor: &Actor,
+        ) -> Result<usize, VickyError>;
+        fn reject_task(
+            &mut self,
+            task_id: Uuid,
+            rejection: &TaskRejection,
+            actor: &Actor,
+        ) -> Result<usize, VickyError>;
+        fn perform_timeout_sweep(
+            &mut self,
+            default_max_runtime: TimeDelta,
+        ) -> Result<TimeoutSweep, VickyError>;
         fn update_task(&mut self, task: &Task) -> Result<usize, VickyError>;
-        fn confirm_task(&mut self, task_id: Uuid) -> Result<usize, VickyError>;
+        fn finish_task(
+            &mut self,
+            task: &Task,
+            poison_locks_on_cancel: bool,
+            actor: &Actor,
+        ) -> Result<usize, VickyError>;
+        fn confirm_task(&mut self, task_id: Uuid, actor: &Actor) -> Result<usize, VickyError>;
         fn has_task(&mut self, task_id: Uuid) -> Result<bool, VickyError>;
         fn has_running_task(&mut self, tid: Uuid) -> Result<bool, VickyError>;
+        fn fail_dependents(&mut self, task_ids: &[Uuid]) -> Result<usize, VickyError>;
+        fn supersede_tasks(
+            &mut self,
+            task_id: Uuid,
+            supersede_key: &str,
+            created_at: DateTime<Utc>,
+        ) -> Result<usize, VickyError>;
+        fn retry_task(&mut self, task: &Task) -> Result<bool, VickyError>;
+        fn rerun_task(
+            &mut self,
+            task: &Task,
+            clear_poison: bool,
+            created_by: Option<Uuid>,
+        ) -> Result<Option<Task>, VickyError>;
+    }
+
+    /// Resolves a task to the id of its latest attempt by following the `retry_of` links.
+    fn latest_attempt_id(
+        conn: &mut diesel::pg::PgConnection,
+        task_id: Uuid,
+    ) -> Result<Uuid, VickyError> {
+        let mut latest = task_id;
+        while let Some(next_attempt) = tasks::table
+            .filter(tasks::retry_of.eq(latest))
+            .select(tasks::id)
+            .first::<Uuid>(conn)
+            .optional()?
+        {
+            latest = next_attempt;
+        }
+        Ok(latest)
+    }
+
+    /// Retries a finished task, or poisons its locks if it failed, and fails its dependents.
+    fn settle_finished_task(
+        conn: &mut diesel::pg::PgConnection,
+        task: &Task,
+    ) -> Result<(), VickyError> {
+        let retried = conn.retry_task(task)?;
+
+        // FIXME: Conversion from DbLock to Lock drops id. No way to update locks here.
+        //        this is just a workaround for now. Should behave fine though
+        //        and is more performant.
+        if task.status.poisons_locks() && !retried {
+            diesel::update(locks::table.filter(locks::task_id.eq(task.id)))
+                .set(locks::poisoned_by_task.eq(task.id))
+                .execute(conn)?;
+        }
+
+        if task.status.is_failed() {
+            conn.fail_dependents(&[task.id])?;
+        }
+
+        Ok(())
     }
 
     impl TaskDatabase for diesel::pg::PgConnection {
         fn count_all_tasks<F: Into<FilterParams>>(
             &mut self,
-            task_status: Option<TaskStatus>,
+            task_statuses: &[TaskStatus],
             filters: F,
         ) -> Result<i64, VickyError> {
             let filters = filters.into();
-            let mut tasks_count_b = tasks::table.into_boxed();
-
-            if let Some(task_status) = task_status {
-                tasks_count_b = tasks_count_b.filter(tasks::status.eq(task_status))
-            }
-
-            if let Some(group) = filters.group {
-                tasks_count_b = tasks_count_b.filter(tasks::group.eq(group))
-            }
-
-            let tasks_count: i64 = tasks_count_b.count().first(self)?;
+            let tasks_count: i64 = filter_tasks(task_statuses, &filters).count().first(self)?;
 
             Ok(tasks_count)
         }
 
         fn get_all_tasks_filtered<F: Into<FilterParams>>(
             &mut self,
-            task_status: Option<TaskStatus>,
+            task_statuses: &[TaskStatus],
             filters: F,
         ) -> Result<Vec<Task>, VickyError> {
             let filters = filters.into();
 
-            let mut db_tasks_build = tasks::table.into_boxed();
-
-            if let Some(task_status) = task_status {
-                db_tasks_build = db_tasks_build.filter(tasks::status.eq(task_status))
+            let mut db_tasks_build = filter_tasks(task_statuses, &filters);
+
+            if let Some(cursor) = filters
+                .task_cursor()
+                .filter(|_| filters.is_sorted_by_creation())
+            {
+                let same_time = tasks::created_at.eq(cursor.created_at);
+                db_tasks_build = match filters.order.unwrap_or_default() {
+                    SortOrder::Asc => db_tasks_build.filter(
+                        tasks::created_at
+                            .gt(cursor.created_at)
+                            .or(same_time.and(tasks::id.gt(cursor.id))),
+                    ),
+                    SortOrder::Desc => db_tasks_build.filter(
+                        tasks::created_at
+                            .lt(cursor.created_at)
+                            .or(same_time.and(tasks::id.lt(cursor.id))),
+                    ),
+                };
             }
 
             if let Some(r_limit) = filters.limit {
@@ -446,36 +1103,41 @@ pub mod db_impl {
             if let Some(r_offset) = filters.offset {
                 db_tasks_build = db_tasks_build.offset(r_offset)
             }
-            if let Some(group) = filters.group {
-                db_tasks_build = db_tasks_build.filter(tasks::group.eq(group))
-            }
-
-            let db_tasks = db_tasks_build
-                .order(tasks::created_at.desc())
-                .load::<DbTask>(self)?;
 
-            // prefetching all locks here, so we don't run into the N+1 Query Problem and distribute them
-            let all_locks = locks::table.load::<DbLock>(self).unwrap_or_else(|_| vec![]);
+            let db_tasks = sort_tasks(db_tasks_build, &filters).load::<DbTask>(self)?;
 
-            let mut lock_map: HashMap<_, Vec<DbLock>> = all_locks
-                .into_iter()
-                .map(|db_lock| (db_lock.task_id, db_lock))
-                .into_group_map();
+            self.load_task_relations(db_tasks)
+        }
 
-            let real_tasks: Vec<Task> = db_tasks
-                .into_iter()
-                .map(|t| {
-                    let real_locks = lock_map.remove(&t.id).unwrap_or_default();
+        fn get_all_tasks(&mut self) -> Result<Vec<Task>, VickyError> {
+            self.get_all_tasks_filtered(&[], None)
+        }
 
-                    (t, real_locks).into()
-                })
-                .collect();
+        fn get_pending_tasks(&mut self) -> Result<Vec<Task>, VickyError> {
+            let mut db_tasks = tasks::table
+                .filter(tasks::status.eq_any(PENDING_STATES))
+                .order(tasks::created_at.desc())
+                .load::<DbTask>(self)?;
 
-            Ok(real_tasks)
-        }
+            // the scheduler needs to know whether finished dependencies of pending tasks succeeded
+            let pending_ids: Vec<Uuid> = db_tasks.iter().map(|t| t.id).collect();
+            let finished_dependency_ids: Vec<Uuid> = task_dependencies::table
+                .inner_join(tasks::table.on(task_dependencies::depends_on.eq(tasks::id)))
+                .filter(task_dependencies::task_id.eq_any(&pending_ids))
+                .filter(tasks::status.ne_all(PENDING_STATES))
+                .select(tasks::id)
+                .distinct()
+                .load(self)?;
+
+            if !finished_dependency_ids.is_empty() {
+                db_tasks.extend(
+                    tasks::table
+                        .filter(tasks::id.eq_any(finished_dependency_ids))
+                        .load::<DbTask>(self)?,
+                );
+            }
 
-        fn get_all_tasks(&mut self) -> Result<Vec<Task>, VickyError> {
-            self.get_all_tasks_filtered(None, None)
+            self.load_task_relations(db_tasks)
         }
 
         fn get_task(&mut self, tid: Uuid) -> Result<Option<Task>, VickyError> {
@@ -487,12 +1149,84 @@ pub mod db_impl {
             let db_locks: Vec<DbLock> = locks::table
                 .filter(locks::task_id.eq(tid))
                 .load::<DbLock>(self)?;
+            let db_dependencies: Vec<DbTaskDependency> = task_dependencies::table
+                .filter(task_dependencies::task_id.eq(tid))
+                .load::<DbTaskDependency>(self)?;
+            let db_labels: Vec<DbTaskLabel> = task_labels::table
+                .filter(task_labels::task_id.eq(tid))
+                .load::<DbTaskLabel>(self)?;
 
-            let task = (db_task, db_locks).into();
+            let task = (db_task, db_locks, db_dependencies, db_labels).into();
 
             Ok(Some(task))
         }
 
+        fn get_task_by_idempotency_key(&mut self, key: &str) -> Result<Option<Task>, VickyError> {
+            let task_id = tasks::table
+                .filter(tasks::idempotency_key.eq(key))
+                .select(tasks::id)
+                .first::<Uuid>(self)
+                .optional()?;
+
+            match task_id {
+                Some(task_id) => self.get_task(task_id),
+                None => Ok(None),
+            }
+        }
+
+        fn get_latest_attempt(&mut self, task_id: Uuid) -> Result<Option<Task>, VickyError> {
+            let task_id = latest_attempt_id(self, task_id)?;
+            self.get_task(task_id)
+        }
+
+        fn claim_next_task(
+            &mut self,
+            features: &[String],
+            config: &SchedulerConfig,
+            actor: &Actor,
+        ) -> Result<Option<Task>, VickyError> {
+            self.transaction(|conn| {
+                // Only one claim may run the scheduler at a time, otherwise two fairies could both
+                // be handed the same task or tasks holding conflicting locks.
+                diesel::sql_query("SELECT pg_advisory_xact_lock($1)")
+                    .bind::<BigInt, _>(CLAIM_ADVISORY_LOCK_KEY)
+                    .execute(conn)?;
+
+                let tasks = conn.get_pending_tasks()?;
+                let poisoned_locks = conn.get_poisoned_locks()?;
+                let scheduler =
+                    Scheduler::new(&tasks, &poisoned_locks, features)?.with_config(config.clone());
+
+                let Some(mut task) = scheduler.get_next_task() else {
+                    return Ok(None);
+                };
+
+                task.status = TaskStatus::Running;
+                task.claimed_at = Some(Utc::now());
+                task.last_heartbeat = task.claimed_at;
+
+                let affected = diesel::update(
+                    tasks::table
+                        .filter(tasks::id.eq(task.id).and(tasks::status.eq(TaskStatus::New))),
+                )
+                .set((
+                    tasks::status.eq(task.status),
+                    tasks::claimed_at.eq(task.claimed_at),
+                    tasks::last_heartbeat.eq(task.last_heartbeat),
+                ))
+                .execute(conn)?;
+
+                if affected == 0 {
+                    return Ok(None);
+                }
+
+                let event = TaskEvent::new(task.id, TaskStatus::New, task.status, actor.clone());
+                conn.put_task_event(&event)?;
+
+                Ok(Some(task))
+            })
+        }
+
         fn put_task(&mut self, task: Task) -> Result<usize, VickyError> {
             self.transaction(|conn| {
                 let db_locks: Vec<NewDbLock> = task
@@ -500,14 +1234,73 @@ pub mod db_impl {
                     .iter()
                     .map(|l| NewDbLock::from_lock(l, task.id))
                     .collect();
+                // a dependency might have been retried since the task was built
+                let mut depends_on = task
+                    .depends_on
+                    .iter()
+                    .map(|&depends_on| latest_attempt_id(conn, depends_on))
+                    .collect::<Result<Vec<_>, _>>()?;
+                depends_on.sort();
+                depends_on.dedup();
+                let db_dependencies: Vec<DbTaskDependency> = depends_on
+                    .into_iter()
+                    .map(|depends_on| DbTaskDependency {
+                        task_id: task.id,
+                        depends_on,
+                    })
+                    .collect();
+                let db_labels: Vec<DbTaskLabel> = task
+                    .labels
+                    .iter()
+                    .map(|(key, value)| DbTaskLabel {
+                        task_id: task.id,
+                        key: key.clone(),
+                        value: value.clone(),
+                    })
+                    .collect();
+                let supersede_key = task.supersede_key.clone();
+                let (task_id, created_at) = (task.id, task.created_at);
                 let db_task: DbTask = task.into();
 
                 let rows_updated = diesel::insert_into(tasks::table)
                     .values(&db_task)
+                    .on_conflict(tasks::idempotency_key)
+                    .do_nothing()
                     .execute(conn)?;
+                if rows_updated == 0 {
+                    // a task with the same idempotency key exists already
+                    return Ok(0);
+                }
                 diesel::insert_into(locks::table)
                     .values(&db_locks)
                     .execute(conn)?;
+                diesel::insert_into(task_labels::table)
+                    .values(&db_labels)
+                    .execute(conn)?;
+
+                if !db_dependencies.is_empty() {
+                    diesel::insert_into(task_dependencies::table)
+                        .values(&db_dependencies)
+                        .execute(conn)?;
+                    // a dependency might have failed in the meantime
+                    let dependencies: Vec<Uuid> = db_dependencies
+                        .iter()
+                        .map(|dependency| dependency.depends_on)
+                        .collect();
+                    conn.fail_dependents(&dependencies)?;
+                }
+
+                if let Some(supersede_key) = supersede_key {
+                    // a task whose dependency failed doesn't replace anything
+                    let status: TaskStatus = tasks::table
+                        .filter(tasks::id.eq(task_id))
+                        .select(tasks::status)
+                        .first(conn)?;
+                    if matches!(status, TaskStatus::New | TaskStatus::NeedsUserValidation) {
+                        conn.supersede_tasks(task_id, &supersede_key, created_at)?;
+                    }
+                }
+
                 Ok(rows_updated)
             })
         }
@@ -538,73 +1331,316 @@ pub mod db_impl {
                         .and(tasks::status.eq(TaskStatus::Running)),
                 ),
             )
-            .set(tasks::status.eq(TaskStatus::Finished(TaskResult::Timeout)))
+            .set((
+                tasks::status.eq(TaskStatus::Finished(TaskResult::Timeout)),
+                tasks::finished_at.eq(Some(Utc::now())),
+            ))
             .execute(self)?;
 
             Ok(rows_updated)
         }
 
-        fn perform_timeout_sweep(&mut self) -> Result<(usize, usize), VickyError> {
-            use crate::database::entities::LockDatabase;
-            let tasks_updated: Vec<DbTask> = diesel::update(
-                tasks::table
+        fn request_task_cancellation(
+            &mut self,
+            task_id: Uuid,
+            actor: &Actor,
+        ) -> Result<usize, VickyError> {
+            self.transaction(|conn| {
+                let rows_updated = diesel::update(
+                    tasks::table
+                        .filter(tasks::id.eq(task_id))
+                        .filter(tasks::status.eq(TaskStatus::Running))
+                        .filter(tasks::cancel_requested_at.is_null()),
+                )
+                .set(tasks::cancel_requested_at.eq(Some(Utc::now())))
+                .execute(conn)?;
+
+                if rows_updated > 0 {
+                    let event = TaskEvent::new(
+                        task_id,
+                        TaskStatus::Running,
+                        TaskStatus::Running,
+                        actor.clone(),
+                    );
+                    conn.put_task_event(&event.with_reason("cancellation requested"))?;
+                }
+
+                Ok(rows_updated)
+            })
+        }
+
+        /// Finishes a pending task as cancelled. Every cancellation ends up here, so `poison_locks`
+        /// decides about the locks of all cancelled tasks. Cancelled tasks are never retried.
+        fn cancel_task(
+            &mut self,
+            task_id: Uuid,
+            poison_locks: bool,
+            actor: &Actor,
+        ) -> Result<usize, VickyError> {
+            self.transaction(|conn| {
+                let Some((status, cancel_requested_at)) = tasks::table
+                    .filter(tasks::id.eq(task_id))
+                    .filter(tasks::status.eq_any(PENDING_STATES))
+                    .select((tasks::status, tasks::cancel_requested_at))
+                    .for_update()
+                    .first::<(TaskStatus, Option<DateTime<Utc>>)>(conn)
+                    .optional()?
+                else {
+                    return Ok(0);
+                };
+
+                let rows_updated = diesel::update(tasks::table.filter(tasks::id.eq(task_id)))
+                    .set((
+                        tasks::status.eq(TaskStatus::Finished(TaskResult::Cancel)),
+                        tasks::finished_at.eq(Some(Utc::now())),
+                    ))
+                    .execute(conn)?;
+
+                let event = TaskEvent::new(
+                    task_id,
+                    status,
+                    TaskStatus::Finished(TaskResult::Cancel),
+                    actor.clone(),
+                );
+                let event = if cancel_requested_at.is_some() {
+                    event.with_reason("the cancellation was requested")
+                } else {
+                    event
+                };
+                conn.put_task_event(&event)?;
+
+                if poison_locks {
+                    conn.poison_all_locks_by_task(task_id)?;
+                }
+                conn.fail_dependents(&[task_id])?;
+
+                Ok(rows_updated)
+            })
+        }
+
+        /// Finishes a task awaiting confirmation as cancelled and stores why it was rejected.
+        /// Nothing ran, so the locks of the task aren't poisoned.
+        fn reject_task(
+            &mut self,
+            task_id: Uuid,
+            rejection: &TaskRejection,
+            actor: &Actor,
+        ) -> Result<usize, VickyError> {
+            self.transaction(|conn| {
+                let rows_updated = diesel::update(
+                    tasks::table
+                        .filter(tasks::id.eq(task_id))
+                        .filter(tasks::status.eq(TaskStatus::NeedsUserValidation)),
+                )
+                .set((
+                    tasks::status.eq(TaskStatus::Finished(TaskResult::Cancel)),
+                    tasks::finished_at.eq(Some(rejection.rejected_at)),
+                    tasks::rejection_comment.eq(Some(&rejection.comment)),
+                    tasks::rejected_at.eq(Some(rejection.rejected_at)),
+                    tasks::rejected_by.eq(rejection.rejected_by),
+                ))
+                .execute(conn)?;
+
+                if rows_updated > 0 {
+                    let event = TaskEvent::new(
+                        task_id,
+                        TaskStatus::NeedsUserValidation,
+                        TaskStatus::Finished(TaskResult::Cancel),
+                        actor.clone(),
+                    );
+                    conn.put_task_event(&event.with_reason(rejection.comment.as_str()))?;
+                    conn.fail_dependents(&[task_id])?;
+                }
+
+                Ok(rows_updated)
+            })
+        }
+
+        fn perform_timeout_sweep(
+            &mut self,
+            default_max_runtime: TimeDelta,
+        ) -> Result<TimeoutSweep, VickyError> {
+            self.transaction(|conn| {
+                let sweep_time = Utc::now();
+
+                let heartbeat_timeouts: Vec<Uuid> = diesel::update(
+                    tasks::table
+                        .filter(tasks::status.eq(TaskStatus::Running))
+                        .filter(tasks::last_heartbeat.is_not_null())
+                        .filter(
+                            (tasks::last_heartbeat.assume_not_null()
+                                + chrono::Duration::seconds(HEARTBEAT_TIMEOUT_SEC))
+                            .le(now),
+                        ),
+                )
+                .set((
+                    tasks::status.eq(TaskStatus::Finished(TaskResult::Timeout)),
+                    tasks::finished_at.eq(Some(sweep_time)),
+                ))
+                .returning(tasks::id)
+                .get_results(conn)?;
+
+                // the runtime limit differs per task, so overdue tasks are picked in here
+                let overdue: Vec<Uuid> = tasks::table
                     .filter(tasks::status.eq(TaskStatus::Running))
-                    .filter(tasks::last_heartbeat.is_not_null())
-                    .filter(
-                        (tasks::last_heartbeat.assume_not_null()
-                            + chrono::Duration::seconds(HEARTBEAT_TIMEOUT_SEC))
-                        .le(now),
-                    ),
-            )
-            .set(tasks::status.eq(TaskStatus::Finished(TaskResult::Timeout)))
-            .get_results::<DbTask>(self)?
-            .into_iter()
-            .collect();
+                    .select((tasks::id, tasks::claimed_at, tasks::max_runtime))
+                    .load::<(Uuid, Option<DateTime<Utc>>, Option<i32>)>(conn)?
+                    .into_iter()
+                    .filter(|(_, claimed_at, max_runtime)| {
+                        let max_runtime = max_runtime
+                            .map(|max_runtime| TimeDelta::seconds(i64::from(max_runtime)))
+                            .unwrap_or(default_max_runtime);
+                        claimed_at.is_some_and(|claimed_at| claimed_at + max_runtime <= sweep_time)
+                    })
+                    .map(|(id, _, _)| id)
+                    .collect();
 
-            let task_rows_updated = tasks_updated.len();
-            let mut lock_rows_updated = 0;
+                let runtime_timeouts: Vec<Uuid> = diesel::update(
+                    tasks::table
+                        .filter(tasks::id.eq_any(overdue))
+                        .filter(tasks::status.eq(TaskStatus::Running)),
+                )
+                .set((
+                    tasks::status.eq(TaskStatus::Finished(TaskResult::Timeout)),
+                    tasks::finished_at.eq(Some(sweep_time)),
+                ))
+                .returning(tasks::id)
+                .get_results(conn)?;
 
-            for task in &tasks_updated {
-                lock_rows_updated += self.poison_all_locks_by_task(task.id)?;
-            }
+                let mut sweep = TimeoutSweep {
+                    heartbeat_timeouts: heartbeat_timeouts.len(),
+                    runtime_timeouts: runtime_timeouts.len(),
+                    poisoned_locks: 0,
+                };
+
+                let timeouts = heartbeat_timeouts
+                    .iter()
+                    .map(|task_id| (*task_id, "no heartbeat was received in time"))
+                    .chain(
+                        runtime_timeouts
+                            .iter()
+                            .map(|task_id| (*task_id, "the maximum runtime was exceeded")),
+                    );
+                for (task_id, reason) in timeouts {
+                    let event = TaskEvent::new(
+                        task_id,
+                        TaskStatus::Running,
+                        TaskStatus::Finished(TaskResult::Timeout),
+                        Actor::System,
+                    );
+                    conn.put_task_event(&event.with_reason(reason))?;
+
+                    let retried = match conn.get_task(task_id)? {
+                        Some(task) => conn.retry_task(&task)?,
+                        None => false,
+                    };
+
+                    if !retried {
+                        sweep.poisoned_locks += conn.poison_all_locks_by_task(task_id)?;
+                    }
+                }
+
+                let timed_out: Vec<Uuid> = heartbeat_timeouts
+                    .into_iter()
+                    .chain(runtime_timeouts)
+                    .collect();
+                conn.fail_dependents(&timed_out)?;
 
-            Ok((task_rows_updated, lock_rows_updated))
+                Ok(sweep)
+            })
         }
 
         fn update_task(&mut self, task: &Task) -> Result<usize, VickyError> {
-            let affected = diesel::update(tasks::table.filter(tasks::id.eq(task.id)))
+            self.transaction(|conn| {
+                let affected = diesel::update(tasks::table.filter(tasks::id.eq(task.id)))
+                    .set((
+                        tasks::status.eq(task.status),
+                        tasks::claimed_at.eq(task.claimed_at),
+                        tasks::finished_at.eq(task.finished_at),
+                        tasks::last_heartbeat.eq(task.last_heartbeat),
+                    ))
+                    .execute(conn)?;
+
+                settle_finished_task(conn, task)?;
+
+                Ok(affected)
+            })
+        }
+
+        /// Finishes a running task with the result it reported. Nothing is changed if the task
+        /// isn't running anymore, e.g. because it timed out in the meantime. Tasks reporting a
+        /// cancellation or whose cancellation was requested are cancelled with `cancel_task`.
+        fn finish_task(
+            &mut self,
+            task: &Task,
+            poison_locks_on_cancel: bool,
+            actor: &Actor,
+        ) -> Result<usize, VickyError> {
+            self.transaction(|conn| {
+                let Some(cancel_requested_at) = tasks::table
+                    .filter(tasks::id.eq(task.id))
+                    .filter(tasks::status.eq(TaskStatus::Running))
+                    .select(tasks::cancel_requested_at)
+                    .for_update()
+                    .first::<Option<DateTime<Utc>>>(conn)
+                    .optional()?
+                else {
+                    return Ok(0);
+                };
+
+                // a killed task reports whatever its process exited with, but it was cancelled
+                if cancel_requested_at.is_some()
+                    || task.status == TaskStatus::Finished(TaskResult::Cancel)
+                {
+                    return conn.cancel_task(task.id, poison_locks_on_cancel, actor);
+                }
+
+                let affected = diesel::update(
+                    tasks::table
+                        .filter(tasks::id.eq(task.id))
+                        .filter(tasks::status.eq(TaskStatus::Running)),
+                )
                 .set((
                     tasks::status.eq(task.status),
-                    tasks::claimed_at.eq(task.claimed_at),
                     tasks::finished_at.eq(task.finished_at),
-                    tasks::last_heartbeat.eq(task.last_heartbeat),
                 ))
-                .execute(self)?;
-
-            // FIXME: Conversion from DbLock to Lock drops id. No way to update locks here.
-            //        this is just a workaround for now. Should behave fine though
-            //        and is more performant.
-            if task.status.is_failed() {
-                diesel::update(locks::table.filter(locks::task_id.eq(task.id)))
-                    .set(locks::poisoned_by_task.eq(task.id))
-                    .execute(self)?;
-            }
+                .execute(conn)?;
 
-            Ok(affected)
-        }
+                if affected > 0 {
+                    let event =
+                        TaskEvent::new(task.id, TaskStatus::Running, task.status, actor.clone());
+                    conn.put_task_event(&event)?;
+                    settle_finished_task(conn, task)?;
+                }
 
-        fn confirm_task(&mut self, task_id: Uuid) -> Result<usize, VickyError> {
-            let affected = diesel::update(
-                tasks::table.filter(
-                    tasks::id
-                        .eq(task_id)
-                        .and(tasks::status.eq(TaskStatus::NeedsUserValidation)),
-                ),
-            )
-            .set(tasks::status.eq(TaskStatus::New))
-            .execute(self)?;
+                Ok(affected)
+            })
+        }
 
-            Ok(affected)
+        fn confirm_task(&mut self, task_id: Uuid, actor: &Actor) -> Result<usize, VickyError> {
+            self.transaction(|conn| {
+                let affected = diesel::update(
+                    tasks::table.filter(
+                        tasks::id
+                            .eq(task_id)
+                            .and(tasks::status.eq(TaskStatus::NeedsUserValidation)),
+                    ),
+                )
+                .set(tasks::status.eq(TaskStatus::New))
+                .execute(conn)?;
+
+                if affected > 0 {
+                    let event = TaskEvent::new(
+                        task_id,
+                        TaskStatus::NeedsUserValidation,
+                        TaskStatus::New,
+                        actor.clone(),
+                    );
+                    conn.put_task_event(&event)?;
+                }
+
+                Ok(affected)
+            })
         }
 
         fn has_task(&mut self, tid: Uuid) -> Result<bool, VickyError> {
@@ -624,5 +1660,214 @@ pub mod db_impl {
 
             Ok(task_count > 0)
         }
+
+        /// Fails the waiting dependents of those of the given tasks that failed, and in turn their
+        /// dependents. Returns how many tasks were failed.
+        fn fail_dependents(&mut self, task_ids: &[Uuid]) -> Result<usize, VickyError> {
+            let failed_states = [
+                TaskStatus::Finished(TaskResult::Error),
+                TaskStatus::Finished(TaskResult::Timeout),
+                TaskStatus::Finished(TaskResult::Cancel),
+                TaskStatus::Finished(TaskResult::DependencyFailed),
+                TaskStatus::Finished(TaskResult::Superseded),
+            ];
+            let waiting_states = [TaskStatus::New, TaskStatus::NeedsUserValidation];
+
+            self.transaction(|conn| {
+                let mut total_affected = 0;
+                let mut failed_tasks = task_ids.to_vec();
+
+                // Every round fails one more level of transitive dependents.
+                while !failed_tasks.is_empty() {
+                    let failed = tasks::table
+                        .filter(tasks::id.eq_any(&failed_tasks))
+                        .filter(tasks::status.eq_any(failed_states))
+                        .select(tasks::id);
+                    let failed_dependencies: HashMap<Uuid, Uuid> = task_dependencies::table
+                        .filter(task_dependencies::depends_on.eq_any(failed))
+                        .select((task_dependencies::task_id, task_dependencies::depends_on))
+                        .load::<(Uuid, Uuid)>(conn)?
+                        .into_iter()
+                        .collect();
+
+                    let mut newly_failed = Vec::new();
+                    // updated per waiting state, so the events know which state the tasks left
+                    for status in waiting_states {
+                        let failed: Vec<Uuid> = diesel::update(
+                            tasks::table
+                                .filter(tasks::id.eq_any(failed_dependencies.keys()))
+                                .filter(tasks::status.eq(status)),
+                        )
+                        .set((
+                            tasks::status.eq(TaskStatus::Finished(TaskResult::DependencyFailed)),
+                            tasks::finished_at.eq(Some(Utc::now())),
+                        ))
+                        .returning(tasks::id)
+                        .get_results(conn)?;
+
+                        for task_id in &failed {
+                            let event = TaskEvent::new(
+                                *task_id,
+                                status,
+                                TaskStatus::Finished(TaskResult::DependencyFailed),
+                                Actor::System,
+                            );
+                            let reason =
+                                format!("dependency {} failed", failed_dependencies[task_id]);
+                            conn.put_task_event(&event.with_reason(reason))?;
+                        }
+                        newly_failed.extend(failed);
+                    }
+
+                    total_affected += newly_failed.len();
+                    failed_tasks = newly_failed;
+                }
+
+                Ok(total_affected)
+            })
+        }
+
+        /// Finishes the tasks with the same `supersede_key` that were created before the task and
+        /// didn't start yet. They never ran, so their locks aren't poisoned. Tasks that depend on
+        /// them wait for the superseding task instead.
+        fn supersede_tasks(
+            &mut self,
+            task_id: Uuid,
+            supersede_key: &str,
+            created_at: DateTime<Utc>,
+        ) -> Result<usize, VickyError> {
+            let waiting_states = [TaskStatus::New, TaskStatus::NeedsUserValidation];
+
+            self.transaction(|conn| {
+                let superseded: Vec<(Uuid, TaskStatus)> = tasks::table
+                    .filter(tasks::supersede_key.eq(supersede_key))
+                    .filter(tasks::id.ne(task_id))
+                    .filter(tasks::created_at.lt(created_at))
+                    .filter(tasks::status.eq_any(waiting_states))
+                    .select((tasks::id, tasks::status))
+                    .for_update()
+                    .load(conn)?;
+                if superseded.is_empty() {
+                    return Ok(0);
+                }
+
+                let superseded_ids: Vec<Uuid> = superseded.iter().map(|(id, _)| *id).collect();
+                diesel::update(tasks::table.filter(tasks::id.eq_any(&superseded_ids)))
+                    .set((
+                        tasks::status.eq(TaskStatus::Finished(TaskResult::Superseded)),
+                        tasks::finished_at.eq(Some(Utc::now())),
+                    ))
+                    .execute(conn)?;
+
+                for (superseded_id, status) in &superseded {
+                    let event = TaskEvent::new(
+                        *superseded_id,
+                        *status,
+                        TaskStatus::Finished(TaskResult::Superseded),
+                        Actor::System,
+                    );
+                    conn.put_task_event(
+                        &event.with_reason(format!("superseded by task {task_id}")),
+                    )?;
+                }
+
+                // finished dependents keep the dependencies they ran with
+                let waiting_dependents: Vec<Uuid> = task_dependencies::table
+                    .filter(task_dependencies::depends_on.eq_any(&superseded_ids))
+                    .filter(
+                        task_dependencies::task_id.eq_any(
+                            tasks::table
+                                .filter(tasks::status.eq_any(waiting_states))
+                                .select(tasks::id),
+                        ),
+                    )
+                    .select(task_dependencies::task_id)
+                    .load::<Uuid>(conn)?
+                    .into_iter()
+                    .unique()
+                    .collect();
+                diesel::delete(
+                    task_dependencies::table
+                        .filter(task_dependencies::depends_on.eq_any(&superseded_ids))
+                        .filter(task_dependencies::task_id.eq_any(&waiting_dependents)),
+                )
+                .execute(conn)?;
+
+                let redirected: Vec<DbTaskDependency> = waiting_dependents
+                    .into_iter()
+                    .filter(|dependent| *dependent != task_id)
+                    .map(|dependent| DbTaskDependency {
+                        task_id: dependent,
+                        depends_on: task_id,
+                    })
+                    .collect();
+                if !redirected.is_empty() {
+                    diesel::insert_into(task_dependencies::table)
+                        .values(&redirected)
+                        .on_conflict_do_nothing()
+                        .execute(conn)?;
+                }
+
+                Ok(superseded.len())
+            })
+        }
+
+        /// Creates the next attempt of a failed task, if its retry policy allows it. Tasks that
+        /// depend on the failed task wait for the new attempt instead.
+        /// Returns whether the task is retried.
+        fn retry_task(&mut self, task: &Task) -> Result<bool, VickyError> {
+            let TaskStatus::Finished(result) = task.status else {
+                return Ok(false);
+            };
+            let Some(next_attempt) = task.next_attempt(result) else {
+                return Ok(false);
+            };
+
+            self.transaction(|conn| {
+                let already_retried: bool = diesel::select(diesel::dsl::exists(
+                    tasks::table.filter(tasks::retry_of.eq(task.id)),
+                ))
+                .get_result(conn)?;
+                if already_retried {
+                    return Ok(true);
+                }
+
+                let next_attempt_id = next_attempt.id;
+                conn.put_task(next_attempt)?;
+
+                diesel::update(
+                    task_dependencies::table.filter(task_dependencies::depends_on.eq(task.id)),
+                )
+                .set(task_dependencies::depends_on.eq(next_attempt_id))
+                .execute(conn)?;
+
+                Ok(true)
+            })
+        }
+
+        /// Puts a re-run of a finished task, optionally clearing the poison it left on its locks.
+        fn rerun_task(
+            &mut self,
+            task: &Task,
+            clear_poison: bool,
+            created_by: Option<Uuid>,
+        ) -> Result<Option<Task>, VickyError> {
+            let Some(mut rerun) = task.rerun() else {
+                return Ok(None);
+            };
+            rerun.created_by = created_by;
+
+            self.transaction(|conn| {
+                if clear_poison {
+                    diesel::update(locks::table.filter(locks::poisoned_by_task.eq(task.id)))
+                        .set(locks::poisoned_by_task.eq(None::<Uuid>))
+                        .execute(conn)?;
+                }
+
+                conn.put_task(rerun.clone())?;
+
+                Ok(Some(rerun))
+            })
+        }
     }
 }
@@ -0,0 +1,162 @@
+//! Tasks with `required_approvals` only run once enough distinct users, other than the creator
+//! and the users who edited them, approved them.
+
+use crate::database::entities::task_approval::db_impl::DbTaskApproval;
+use chrono::serde::ts_seconds;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TaskApproval {
+    pub task_id: Uuid,
+    pub user_id: Uuid,
+
+    #[serde(with = "ts_seconds")]
+    pub approved_at: DateTime<Utc>,
+}
+
+/// What became of an approval.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApprovalOutcome {
+    /// The approval was recorded, the task still needs more of them.
+    Recorded,
+    /// The approval was the last one the task needed, it can run now.
+    Confirmed,
+    /// The user created or edited the task, so they can't approve it.
+    Forbidden,
+    /// The task doesn't await confirmation anymore.
+    NotAwaitingConfirmation,
+}
+
+impl From<DbTaskApproval> for TaskApproval {
+    fn from(approval: DbTaskApproval) -> Self {
+        TaskApproval {
+            task_id: approval.task_id,
+            user_id: approval.user_id,
+            approved_at: approval.approved_at,
+        }
+    }
+}
+
+pub mod db_impl {
+    use chrono::{DateTime, Utc};
+    use diesel::prelude::*;
+    use serde::Serialize;
+    use uuid::Uuid;
+
+    use crate::database::entities::Task;
+    use crate::database::entities::task::TaskStatus;
+    use crate::database::entities::task_approval::{ApprovalOutcome, TaskApproval};
+    use crate::database::entities::task_event::db_impl::TaskEventDatabase;
+    use crate::database::entities::task_event::{Actor, TaskEvent};
+    use crate::database::schema::{task_approvals, task_edits, tasks};
+    use crate::errors::VickyError;
+
+    #[derive(Insertable, Queryable, Debug, Serialize)]
+    #[diesel(table_name = task_approvals)]
+    pub struct DbTaskApproval {
+        pub task_id: Uuid,
+        pub user_id: Uuid,
+        pub approved_at: DateTime<Utc>,
+    }
+
+    pub trait TaskApprovalDatabase {
+        fn approve_task(
+            &mut self,
+            task: &Task,
+            user_id: Uuid,
+        ) -> Result<ApprovalOutcome, VickyError>;
+        fn get_task_approvals(&mut self, task_id: Uuid) -> Result<Vec<TaskApproval>, VickyError>;
+    }
+
+    impl TaskApprovalDatabase for PgConnection {
+        /// Records the approval of a user and confirms the task once it has enough approvals.
+        /// Approving a task twice has no effect. Neither the creator nor a user who edited the
+        /// task may approve it. Every edit discards the approvals, so an editor can't have
+        /// approved the task in its current form before.
+        fn approve_task(
+            &mut self,
+            task: &Task,
+            user_id: Uuid,
+        ) -> Result<ApprovalOutcome, VickyError> {
+            self.transaction(|conn| {
+                // Concurrent approvals wait for each other, otherwise neither of them might see
+                // the other one and the task would never be confirmed.
+                let status = tasks::table
+                    .filter(tasks::id.eq(task.id))
+                    .select(tasks::status)
+                    .for_update()
+                    .first::<TaskStatus>(conn)
+                    .optional()?;
+                if status != Some(TaskStatus::NeedsUserValidation) {
+                    return Ok(ApprovalOutcome::NotAwaitingConfirmation);
+                }
+
+                let edited: bool = diesel::select(diesel::dsl::exists(
+                    task_edits::table
+                        .filter(task_edits::task_id.eq(task.id))
+                        .filter(task_edits::edited_by.eq(user_id)),
+                ))
+                .get_result(conn)?;
+                if task.created_by == Some(user_id) || edited {
+                    return Ok(ApprovalOutcome::Forbidden);
+                }
+
+                let approval = DbTaskApproval {
+                    task_id: task.id,
+                    user_id,
+                    approved_at: Utc::now(),
+                };
+                diesel::insert_into(task_approvals::table)
+                    .values(&approval)
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+
+                let approvals: i64 = task_approvals::table
+                    .filter(task_approvals::task_id.eq(task.id))
+                    .count()
+                    .get_result(conn)?;
+                if approvals < i64::from(task.required_approvals) {
+                    return Ok(ApprovalOutcome::Recorded);
+                }
+
+                let confirmed = diesel::update(
+                    tasks::table
+                        .filter(tasks::id.eq(task.id))
+                        .filter(tasks::status.eq(TaskStatus::NeedsUserValidation)),
+                )
+                .set(tasks::status.eq(TaskStatus::New))
+                .execute(conn)?;
+
+                if confirmed > 0 {
+                    let event = TaskEvent::new(
+                        task.id,
+                        TaskStatus::NeedsUserValidation,
+                        TaskStatus::New,
+                        Actor::User { id: user_id },
+                    );
+                    let reason = format!("approved by {} users", task.required_approvals);
+                    conn.put_task_event(&event.with_reason(reason))?;
+                }
+
+                match confirmed {
+                    0 => Ok(ApprovalOutcome::NotAwaitingConfirmation),
+                    _ => Ok(ApprovalOutcome::Confirmed),
+                }
+            })
+        }
+
+        fn get_task_approvals(&mut self, task_id: Uuid) -> Result<Vec<TaskApproval>, VickyError> {
+            let approvals = task_approvals::table
+                .filter(task_approvals::task_id.eq(task_id))
+                .order(task_approvals::approved_at.asc())
+                .load::<DbTaskApproval>(self)?
+                .into_iter()
+                .map(TaskApproval::from)
+                .collect();
+
+            Ok(approvals)
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Tasks awaiting confirmation may still be edited. Every edit is recorded with the changed
+//! fields and their previous values.
+
+use crate::database::entities::task_edit::db_impl::DbTaskEdit;
+use chrono::serde::ts_seconds;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub from: Value,
+    pub to: Value,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TaskEdit {
+    pub id: Uuid,
+    pub task_id: Uuid,
+
+    #[serde(with = "ts_seconds")]
+    pub edited_at: DateTime<Utc>,
+
+    /// The user who edited the task, `None` for machines.
+    pub edited_by: Option<Uuid>,
+
+    pub changes: BTreeMap<String, FieldChange>,
+}
+
+impl TaskEdit {
+    pub fn new(task_id: Uuid, edited_by: Option<Uuid>) -> Self {
+        TaskEdit {
+            id: Uuid::new_v4(),
+            task_id,
+            edited_at: Utc::now(),
+            edited_by,
+            changes: BTreeMap::new(),
+        }
+    }
+
+    /// Sets `current` to `new`, recording the change if there is one.
+    pub fn record<T: Serialize + PartialEq>(
+        &mut self,
+        field: &str,
+        current: &mut T,
+        new: Option<T>,
+    ) {
+        let Some(new) = new else {
+            return;
+        };
+        if *current == new {
+            return;
+        }
+
+        let change = FieldChange {
+            from: serde_json::to_value(&*current).unwrap_or_default(),
+            to: serde_json::to_value(&new).unwrap_or_default(),
+        };
+        self.changes.insert(field.to_string(), change);
+        *current = new;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl AsRef<TaskEdit> for TaskEdit {
+    fn as_ref(&self) -> &TaskEdit {
+        self
+    }
+}
+
+impl From<DbTaskEdit> for TaskEdit {
+    fn from(edit: DbTaskEdit) -> Self {
+        TaskEdit {
+            id: edit.id,
+            task_id: edit.task_id,
+            edited_at: edit.edited_at,
+            edited_by: edit.edited_by,
+            changes: serde_json::from_value(edit.changes).unwrap_or_default(),
+        }
+    }
+}
+
+pub mod db_impl {
+    use chrono::{DateTime, Utc};
+    use diesel::prelude::*;
+    use serde::Serialize;
+    use uuid::Uuid;
+
+    use crate::database::entities::Task;
+    use crate::database::entities::lock::db_impl::NewDbLock;
+    use crate::database::entities::task::TaskStatus;
+    use crate::database::entities::task_edit::TaskEdit;
+    use crate::database::schema::{locks, task_approvals, task_edits, tasks};
+    use crate::errors::VickyError;
+
+    #[derive(Insertable, Queryable, Debug, Serialize)]
+    #[diesel(table_name = task_edits)]
+    pub struct DbTaskEdit {
+        pub id: Uuid,
+        pub task_id: Uuid,
+        pub edited_at: DateTime<Utc>,
+        pub edited_by: Option<Uuid>,
+        pub changes: serde_json::Value,
+    }
+
+    impl From<&TaskEdit> for DbTaskEdit {
+        fn from(edit: &TaskEdit) -> Self {
+            DbTaskEdit {
+                id: edit.id,
+                task_id: edit.task_id,
+                edited_at: edit.edited_at,
+                edited_by: edit.edited_by,
+                changes: serde_json::to_value(&edit.changes).unwrap_or_default(),
+            }
+        }
+    }
+
+    pub trait TaskEditDatabase {
+        fn edit_task(&mut self, task: &Task, edit: &TaskEdit) -> Result<usize, VickyError>;
+        fn get_task_edits(&mut self, task_id: Uuid) -> Result<Vec<TaskEdit>, VickyError>;
+    }
+
+    impl TaskEditDatabase for PgConnection {
+        /// Stores the edited spec of a task and records the edit.
+        /// Only tasks that still await confirmation are updated.
+        fn edit_task(&mut self, task: &Task, edit: &TaskEdit) -> Result<usize, VickyError> {
+            self.transaction(|conn| {
+                let affected = diesel::update(
+                    tasks::table
+                        .filter(tasks::id.eq(task.id))
+                        .filter(tasks::status.eq(TaskStatus::NeedsUserValidation)),
+                )
+                .set((
+                    tasks::display_name.eq(&task.display_name),
+                    tasks::flake_ref_args.eq(&task.flake_ref.args),
+                    tasks::features.eq(&task.features),
+                    tasks::group.eq(&task.group),
+                    tasks::priority.eq(task.priority),
+                ))
+                .execute(conn)?;
+
+                if affected == 0 {
+                    return Ok(0);
+                }
+
+                // approvals were given for the previous spec
+                diesel::delete(task_approvals::table.filter(task_approvals::task_id.eq(task.id)))
+                    .execute(conn)?;
+
+                if edit.changes.contains_key("locks") {
+                    let db_locks: Vec<NewDbLock> = task
+                        .locks
+                        .iter()
+                        .map(|lock| NewDbLock::from_lock(lock, task.id))
+                        .collect();
+
+                    diesel::delete(locks::table.filter(locks::task_id.eq(task.id)))
+                        .execute(conn)?;
+                    diesel::insert_into(locks::table)
+                        .values(&db_locks)
+                        .execute(conn)?;
+                }
+
+                diesel::insert_into(task_edits::table)
+                    .values(DbTaskEdit::from(edit))
+                    .execute(conn)?;
+
+                Ok(affected)
+            })
+        }
+
+        fn get_task_edits(&mut self, task_id: Uuid) -> Result<Vec<TaskEdit>, VickyError> {
+            let edits = task_edits::table
+                .filter(task_edits::task_id.eq(task_id))
+                .order(task_edits::edited_at.asc())
+                .load::<DbTaskEdit>(self)?
+                .into_iter()
+                .map(TaskEdit::from)
+                .collect();
+
+            Ok(edits)
+        }
+    }
+}
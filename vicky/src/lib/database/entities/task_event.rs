@@ -0,0 +1,164 @@
+//! Every status change of a task is recorded as an event, together with who caused it.
+
+use crate::database::entities::task::TaskStatus;
+use crate::database::entities::task_event::db_impl::DbTaskEvent;
+use chrono::serde::ts_seconds;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Actor {
+    User {
+        id: Uuid,
+    },
+    Machine {
+        name: String,
+    },
+    /// Vicky itself, e.g. when timing out tasks.
+    System,
+}
+
+impl AsRef<Actor> for Actor {
+    fn as_ref(&self) -> &Actor {
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TaskEvent {
+    pub id: Uuid,
+    pub task_id: Uuid,
+
+    #[serde(with = "ts_seconds")]
+    pub created_at: DateTime<Utc>,
+
+    pub from_status: TaskStatus,
+    pub to_status: TaskStatus,
+    pub actor: Actor,
+    pub reason: Option<String>,
+}
+
+impl TaskEvent {
+    pub fn new(
+        task_id: Uuid,
+        from_status: TaskStatus,
+        to_status: TaskStatus,
+        actor: Actor,
+    ) -> Self {
+        TaskEvent {
+            id: Uuid::new_v4(),
+            task_id,
+            created_at: Utc::now(),
+            from_status,
+            to_status,
+            actor,
+            reason: None,
+        }
+    }
+
+    pub fn with_reason<S: Into<String>>(mut self, reason: S) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+}
+
+impl AsRef<TaskEvent> for TaskEvent {
+    fn as_ref(&self) -> &TaskEvent {
+        self
+    }
+}
+
+impl From<DbTaskEvent> for TaskEvent {
+    fn from(event: DbTaskEvent) -> Self {
+        let actor = match (event.actor_user, event.actor_machine) {
+            (Some(id), _) => Actor::User { id },
+            (None, Some(name)) => Actor::Machine { name },
+            (None, None) => Actor::System,
+        };
+
+        TaskEvent {
+            id: event.id,
+            task_id: event.task_id,
+            created_at: event.created_at,
+            from_status: event.from_status,
+            to_status: event.to_status,
+            actor,
+            reason: event.reason,
+        }
+    }
+}
+
+pub mod db_impl {
+    use chrono::{DateTime, Utc};
+    use diesel::prelude::*;
+    use serde::Serialize;
+    use uuid::Uuid;
+
+    use crate::database::entities::task::TaskStatus;
+    use crate::database::entities::task_event::{Actor, TaskEvent};
+    use crate::database::schema::task_events;
+    use crate::errors::VickyError;
+
+    #[derive(Insertable, Queryable, Debug, Serialize)]
+    #[diesel(table_name = task_events)]
+    pub struct DbTaskEvent {
+        pub id: Uuid,
+        pub task_id: Uuid,
+        pub created_at: DateTime<Utc>,
+        pub from_status: TaskStatus,
+        pub to_status: TaskStatus,
+        pub actor_user: Option<Uuid>,
+        pub actor_machine: Option<String>,
+        pub reason: Option<String>,
+    }
+
+    impl From<&TaskEvent> for DbTaskEvent {
+        fn from(event: &TaskEvent) -> Self {
+            let (actor_user, actor_machine) = match &event.actor {
+                Actor::User { id } => (Some(*id), None),
+                Actor::Machine { name } => (None, Some(name.clone())),
+                Actor::System => (None, None),
+            };
+
+            DbTaskEvent {
+                id: event.id,
+                task_id: event.task_id,
+                created_at: event.created_at,
+                from_status: event.from_status,
+                to_status: event.to_status,
+                actor_user,
+                actor_machine,
+                reason: event.reason.clone(),
+            }
+        }
+    }
+
+    pub trait TaskEventDatabase {
+        fn put_task_event(&mut self, event: &TaskEvent) -> Result<usize, VickyError>;
+        fn get_task_events(&mut self, task_id: Uuid) -> Result<Vec<TaskEvent>, VickyError>;
+    }
+
+    impl TaskEventDatabase for PgConnection {
+        fn put_task_event(&mut self, event: &TaskEvent) -> Result<usize, VickyError> {
+            let rows_inserted = diesel::insert_into(task_events::table)
+                .values(DbTaskEvent::from(event))
+                .execute(self)?;
+
+            Ok(rows_inserted)
+        }
+
+        fn get_task_events(&mut self, task_id: Uuid) -> Result<Vec<TaskEvent>, VickyError> {
+            let events = task_events::table
+                .filter(task_events::task_id.eq(task_id))
+                .order(task_events::created_at.asc())
+                .load::<DbTaskEvent>(self)?
+                .into_iter()
+                .map(TaskEvent::from)
+                .collect();
+
+            Ok(events)
+        }
+    }
+}
@@ -11,6 +11,85 @@ diesel::table! {
         #[sql_name = "type"]
         lock_type -> LockKindSqlType,
         poisoned_by_task -> Nullable<Uuid>,
+        capacity -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::database::entities::lock::db_impl::LockKindSqlType;
+
+    schedule_locks (id) {
+        id -> Uuid,
+        schedule_id -> Uuid,
+        name -> Varchar,
+        #[sql_name = "type"]
+        lock_type -> LockKindSqlType,
+        capacity -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    schedules (id) {
+        id -> Uuid,
+        display_name -> Varchar,
+        cron -> Varchar,
+        flake_ref_uri -> Varchar,
+        flake_ref_args -> Array<Text>,
+        features -> Array<Text>,
+        group -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+        next_run_at -> Nullable<Timestamptz>,
+        last_task_id -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    task_approvals (task_id, user_id) {
+        task_id -> Uuid,
+        user_id -> Uuid,
+        approved_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    task_dependencies (task_id, depends_on) {
+        task_id -> Uuid,
+        depends_on -> Uuid,
+    }
+}
+
+diesel::table! {
+    task_labels (task_id, key) {
+        task_id -> Uuid,
+        key -> Varchar,
+        value -> Varchar,
+    }
+}
+
+diesel::table! {
+    task_edits (id) {
+        id -> Uuid,
+        task_id -> Uuid,
+        edited_at -> Timestamptz,
+        edited_by -> Nullable<Uuid>,
+        changes -> Jsonb,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::database::entities::task::db_impl::TaskStatusSqlType;
+
+    task_events (id) {
+        id -> Uuid,
+        task_id -> Uuid,
+        created_at -> Timestamptz,
+        from_status -> TaskStatusSqlType,
+        to_status -> TaskStatusSqlType,
+        actor_user -> Nullable<Uuid>,
+        actor_machine -> Nullable<Varchar>,
+        reason -> Nullable<Varchar>,
     }
 }
 
@@ -30,6 +109,24 @@ diesel::table! {
         finished_at -> Nullable<Timestamptz>,
         last_heartbeat -> Nullable<Timestamptz>,
         group -> Nullable<Varchar>,
+        priority -> Int4,
+        not_before -> Nullable<Timestamptz>,
+        attempt -> Int4,
+        retry_of -> Nullable<Uuid>,
+        retry_max_attempts -> Nullable<Int4>,
+        retry_backoff_sec -> Nullable<Int4>,
+        retry_on -> Nullable<Array<Text>>,
+        max_runtime -> Nullable<Int4>,
+        cancel_requested_at -> Nullable<Timestamptz>,
+        needs_confirmation -> Bool,
+        rerun_of -> Nullable<Uuid>,
+        required_approvals -> Int4,
+        created_by -> Nullable<Uuid>,
+        rejection_comment -> Nullable<Varchar>,
+        rejected_at -> Nullable<Timestamptz>,
+        rejected_by -> Nullable<Uuid>,
+        idempotency_key -> Nullable<Varchar>,
+        supersede_key -> Nullable<Varchar>,
     }
 }
 
@@ -44,4 +141,15 @@ diesel::table! {
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(locks, tasks, users,);
+diesel::allow_tables_to_appear_in_same_query!(
+    locks,
+    schedule_locks,
+    schedules,
+    task_approvals,
+    task_dependencies,
+    task_edits,
+    task_events,
+    task_labels,
+    tasks,
+    users,
+);
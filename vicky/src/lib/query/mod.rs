@@ -1,10 +1,150 @@
-use rocket::FromForm;
+use crate::database::entities::Task;
+use chrono::{DateTime, Utc};
+use rocket::{FromForm, FromFormField};
+use uuid::Uuid;
+
+#[derive(FromFormField, clap::ValueEnum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TaskSort {
+    #[default]
+    #[field(value = "created_at")]
+    #[value(name = "created_at")]
+    CreatedAt,
+    /// Unfinished tasks are sorted last, regardless of the order.
+    #[field(value = "finished_at")]
+    #[value(name = "finished_at")]
+    FinishedAt,
+    #[field(value = "priority")]
+    #[value(name = "priority")]
+    Priority,
+    #[field(value = "display_name")]
+    #[value(name = "display_name")]
+    DisplayName,
+}
+
+impl TaskSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskSort::CreatedAt => "created_at",
+            TaskSort::FinishedAt => "finished_at",
+            TaskSort::Priority => "priority",
+            TaskSort::DisplayName => "display_name",
+        }
+    }
+}
+
+#[derive(FromFormField, clap::ValueEnum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    #[field(value = "asc")]
+    #[value(name = "asc")]
+    Asc,
+    #[default]
+    #[field(value = "desc")]
+    #[value(name = "desc")]
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+pub const NEXT_CURSOR_HEADER: &str = "Next-Cursor";
+
+/// Points at the last task of a page. The next page starts right after it in the order of
+/// (`created_at`, `id`), so tasks that are added in the meantime don't shift the pages.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TaskCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl TaskCursor {
+    pub fn after(task: &Task) -> Self {
+        TaskCursor {
+            created_at: task.created_at,
+            id: task.id,
+        }
+    }
+
+    /// Encodes the cursor as an opaque token of 48 hex digits.
+    pub fn encode(&self) -> String {
+        format!(
+            "{:016x}{}",
+            self.created_at.timestamp_micros(),
+            self.id.simple()
+        )
+    }
+
+    pub fn decode(token: &str) -> Option<Self> {
+        let micros = token.get(..16)?;
+        let id = token.get(16..)?;
+        if id.len() != 32 {
+            return None;
+        }
+
+        let micros = u64::from_str_radix(micros, 16).ok()? as i64;
+        Some(TaskCursor {
+            created_at: DateTime::from_timestamp_micros(micros)?,
+            id: Uuid::try_parse(id).ok()?,
+        })
+    }
+}
 
 #[derive(FromForm, Default, Clone)]
 pub struct FilterParams {
     pub limit: Option<i64>,
     pub offset: Option<i64>,
     pub group: Option<String>,
+    /// Label selectors, either `key=value` or just `key` to match any value.
+    pub label: Vec<String>,
+    /// Unix timestamps in seconds, the lower bounds are inclusive and the upper bounds exclusive.
+    pub created_after: Option<i64>,
+    pub created_before: Option<i64>,
+    pub finished_after: Option<i64>,
+    pub finished_before: Option<i64>,
+    /// Name of a lock the task holds.
+    pub lock: Option<String>,
+    /// Substring of the flake URI.
+    pub flake: Option<String>,
+    /// Case-insensitive substring of the display name.
+    pub search: Option<String>,
+    pub sort: Option<TaskSort>,
+    pub order: Option<SortOrder>,
+    /// Token of a [`TaskCursor`], only tasks after it are listed. Requires sorting by `created_at`
+    /// and can't be combined with `offset`.
+    pub cursor: Option<String>,
+}
+
+impl FilterParams {
+    pub fn label_selectors(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.label
+            .iter()
+            .map(|selector| match selector.split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (selector.as_str(), None),
+            })
+    }
+
+    pub fn task_cursor(&self) -> Option<TaskCursor> {
+        self.cursor.as_deref().and_then(TaskCursor::decode)
+    }
+
+    pub fn is_sorted_by_creation(&self) -> bool {
+        self.sort.unwrap_or_default() == TaskSort::CreatedAt
+    }
+
+    /// The cursor of the page following `tasks`, if it is a full page.
+    pub fn next_cursor(&self, tasks: &[Task]) -> Option<TaskCursor> {
+        let limit = usize::try_from(self.limit?).ok()?;
+        if !self.is_sorted_by_creation() || limit == 0 || tasks.len() < limit {
+            return None;
+        }
+        tasks.last().map(TaskCursor::after)
+    }
 }
 
 impl From<Option<FilterParams>> for FilterParams {
@@ -12,3 +152,12 @@ impl From<Option<FilterParams>> for FilterParams {
         value.unwrap_or_default()
     }
 }
+
+/// Escapes the wildcards of a `LIKE` pattern, so `value` is matched literally.
+pub fn like_pattern(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{escaped}%")
+}
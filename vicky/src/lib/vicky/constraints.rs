@@ -1,6 +1,10 @@
+use crate::database::entities::lock::lock_names_overlap;
+use crate::database::entities::task::{TaskResult, TaskStatus};
 use crate::database::entities::{Lock, Task};
 use crate::errors::SchedulerError;
+use chrono::{DateTime, Utc};
 use std::collections::HashMap;
+use uuid::Uuid;
 
 #[derive(Clone, Debug)]
 #[allow(unused)]
@@ -8,7 +12,11 @@ pub enum ConstraintFail<'a> {
     UnsupportedFeature(String),
     ActiveLockCollision(&'a Lock),
     PassiveLockCollision(&'a Lock),
+    HeldBackForWriter(&'a Lock),
     PoisonedBy(&'a Lock),
+    WaitingForDependency(Uuid),
+    Deferred(DateTime<Utc>),
+    GroupLimitReached { group: String, max_running: usize },
 }
 
 #[derive(Clone, Debug)]
@@ -22,12 +30,17 @@ pub enum ConstraintEvaluation<'a> {
 #[derive(Clone, Debug, Default)]
 pub struct Constraints<'a> {
     /// Takes all active locks and validates running ownership
-    active_locks: HashMap<&'a str, &'a Lock>,
+    active_locks: HashMap<&'a str, Vec<&'a Lock>>,
     /// Takes all non-running locks that need to be considered for future acquires
     passive_locks: HashMap<&'a str, Vec<&'a Lock>>,
     /// Takes all non-running locks that need to be considered for cleanup order
     waiting_locks: HashMap<&'a str, Vec<&'a Lock>>,
     poisoned_locks: &'a [Lock],
+    /// Status of every known task, used to resolve task dependencies
+    task_states: HashMap<Uuid, TaskStatus>,
+    /// Number of running tasks per task group
+    running_groups: HashMap<&'a str, usize>,
+    running_ungrouped: usize,
 }
 
 impl<'a> Constraints<'a> {
@@ -46,11 +59,11 @@ impl<'a> Constraints<'a> {
     }
 
     fn insert_active_lock(&mut self, lock: &'a Lock) -> Result<(), SchedulerError> {
-        if self.is_actively_locked(lock) {
+        if self.is_actively_locked(lock) || self.find_capacity_conflict(lock).is_some() {
             return Err(SchedulerError::LockAlreadyOwnedError);
         }
 
-        self.active_locks.insert(lock.name(), lock);
+        self.active_locks.entry(lock.name()).or_default().push(lock);
 
         Ok(())
     }
@@ -78,6 +91,10 @@ impl<'a> Constraints<'a> {
             return Some(ConstraintFail::ActiveLockCollision(conflict));
         }
 
+        if let Some(conflict) = self.find_capacity_conflict(lock) {
+            return Some(ConstraintFail::ActiveLockCollision(conflict));
+        }
+
         if let Some(conflict) = self.find_passive_conflict(lock) {
             return Some(ConstraintFail::PassiveLockCollision(conflict));
         }
@@ -97,31 +114,68 @@ impl<'a> Constraints<'a> {
         self.find_active_conflict(lock).is_some()
     }
 
-    #[allow(unused)]
-    fn is_poisoned(&self, lock: &Lock) -> bool {
+    pub fn is_poisoned(&self, lock: &Lock) -> bool {
         self.find_poisoner(lock).is_some()
     }
 
+    /// Yields all entries whose lock name is the same path, a parent path or a child path of `lock`.
+    fn overlapping<'m, T>(
+        locks: &'m HashMap<&'a str, T>,
+        lock: &'m Lock,
+    ) -> impl Iterator<Item = &'m T> {
+        locks
+            .iter()
+            .filter(|(name, _)| lock_names_overlap(name, lock.name()))
+            .map(|(_, entry)| entry)
+    }
+
     fn find_active_conflict(&self, lock: &Lock) -> Option<&'a Lock> {
-        let existing_lock = self.active_locks.get(lock.name())?;
+        Self::overlapping(&self.active_locks, lock)
+            .flatten()
+            .copied()
+            .find(|existing_lock| {
+                lock.kind.is_cleanup()
+                    || existing_lock.kind.is_cleanup()
+                    || lock.is_conflicting(existing_lock)
+            })
+    }
 
-        if lock.kind.is_cleanup() || existing_lock.kind.is_cleanup() {
-            return Some(existing_lock);
+    /// Holders of the same semaphore don't conflict with each other, but only `capacity` of them
+    /// may be held at once. If the holders disagree on the capacity, the smallest one applies, so
+    /// the outcome doesn't depend on which task is evaluated first.
+    fn find_capacity_conflict(&self, lock: &Lock) -> Option<&'a Lock> {
+        if !lock.kind.is_semaphore() {
+            return None;
         }
 
-        lock.is_conflicting(existing_lock).then_some(existing_lock)
+        let holders: Vec<&'a Lock> = self
+            .active_locks
+            .get(lock.name())
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|existing_lock| existing_lock.kind.is_semaphore())
+            .collect();
+        let capacity = holders
+            .iter()
+            .map(|holder| holder.capacity())
+            .fold(lock.capacity(), usize::min);
+
+        if holders.len() < capacity {
+            return None;
+        }
+
+        holders.first().copied()
     }
 
     fn find_passive_conflict(&self, lock: &Lock) -> Option<&'a Lock> {
-        let existing_locks = self.passive_locks.get(lock.name())?;
+        let mut existing_locks = Self::overlapping(&self.passive_locks, lock).flatten();
 
         if lock.kind.is_cleanup() {
-            let existing_lock = *existing_locks.first()?;
-            return Some(existing_lock);
+            return existing_locks.next().copied();
         }
 
         existing_locks
-            .iter()
             .copied()
             .find(|existing_lock| lock.is_conflicting(existing_lock))
     }
@@ -132,13 +186,32 @@ impl<'a> Constraints<'a> {
             .find(|plock| lock.is_conflicting(plock))
     }
 
+    /// Dependencies that aren't known anymore are considered to be finished successfully.
+    /// Failed dependencies are handled by failing the dependent task in the database.
+    pub fn find_unfinished_dependency(&self, task: &Task) -> Option<Uuid> {
+        task.depends_on.iter().copied().find(|dependency| {
+            self.task_states
+                .get(dependency)
+                .is_some_and(|status| *status != TaskStatus::Finished(TaskResult::Success))
+        })
+    }
+
+    pub fn running_in_group(&self, group: Option<&str>) -> usize {
+        match group {
+            Some(group) => self.running_groups.get(group).copied().unwrap_or(0),
+            None => self.running_ungrouped,
+        }
+    }
+
     fn find_cleanup_conflict(&self, lock: &Lock) -> Option<&'a Lock> {
         if !lock.kind.is_cleanup() {
             return None;
         }
 
-        let existing_locks = self.waiting_locks.get(lock.name())?;
-        existing_locks.first().copied()
+        Self::overlapping(&self.waiting_locks, lock)
+            .flatten()
+            .next()
+            .copied()
     }
 
     pub fn from_tasks(
@@ -152,6 +225,13 @@ impl<'a> Constraints<'a> {
 
         for task in tasks {
             constraints.insert_task_locks(task)?;
+            constraints.task_states.insert(task.id, task.status);
+            if task.is_running() {
+                match task.group.as_deref() {
+                    Some(group) => *constraints.running_groups.entry(group).or_default() += 1,
+                    None => constraints.running_ungrouped += 1,
+                }
+            }
         }
 
         Ok(constraints)
@@ -163,10 +243,26 @@ impl ConstraintEvaluation<'_> {
         ConstraintEvaluation::Constrained(ConstraintFail::UnsupportedFeature(feature))
     }
 
+    pub fn waiting_for_dependency(task_id: Uuid) -> Self {
+        ConstraintEvaluation::Constrained(ConstraintFail::WaitingForDependency(task_id))
+    }
+
+    pub fn deferred(not_before: DateTime<Utc>) -> Self {
+        ConstraintEvaluation::Constrained(ConstraintFail::Deferred(not_before))
+    }
+
     pub fn is_ready(&self) -> bool {
         matches!(self, ConstraintEvaluation::Ready)
     }
 
+    #[allow(unused)]
+    pub fn is_deferred(&self) -> bool {
+        matches!(
+            self,
+            ConstraintEvaluation::Constrained(ConstraintFail::Deferred(_))
+        )
+    }
+
     #[allow(unused)]
     pub fn is_active_collision(&self) -> bool {
         matches!(
@@ -175,6 +271,14 @@ impl ConstraintEvaluation<'_> {
         )
     }
 
+    #[allow(unused)]
+    pub fn is_waiting_for_dependency(&self) -> bool {
+        matches!(
+            self,
+            ConstraintEvaluation::Constrained(ConstraintFail::WaitingForDependency(_))
+        )
+    }
+
     #[allow(unused)]
     pub fn is_passive_collision(&self) -> bool {
         matches!(
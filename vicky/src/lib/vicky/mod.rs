@@ -1,2 +1,3 @@
 mod constraints;
+pub mod readiness;
 pub mod scheduler;
@@ -0,0 +1,108 @@
+use crate::database::entities::Lock;
+use crate::database::entities::task::TaskStatus;
+use chrono::serde::ts_seconds;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use uuid::Uuid;
+
+/// Serializable explanation of why a task can or can't be claimed right now.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TaskReadiness {
+    Ready,
+    NotNew {
+        status: TaskStatus,
+    },
+    MissingFeature {
+        feature: String,
+    },
+    WaitingForDependency {
+        task_id: Uuid,
+    },
+    Deferred {
+        #[serde(with = "ts_seconds")]
+        not_before: DateTime<Utc>,
+    },
+    ActiveLockCollision {
+        lock: Lock,
+        held_by: Option<Uuid>,
+    },
+    PassiveLockCollision {
+        lock: Lock,
+        held_by: Option<Uuid>,
+    },
+    HeldBackForWriter {
+        lock: Lock,
+        writer: Option<Uuid>,
+    },
+    PoisonedBy {
+        lock: Lock,
+        task_id: Option<Uuid>,
+    },
+    GroupLimitReached {
+        group: String,
+        max_running: usize,
+    },
+}
+
+impl TaskReadiness {
+    pub fn is_ready(&self) -> bool {
+        matches!(self, TaskReadiness::Ready)
+    }
+}
+
+fn fmt_task(task_id: &Option<Uuid>) -> String {
+    task_id.map_or_else(|| "an unknown task".to_string(), |id| format!("task {id}"))
+}
+
+impl Display for TaskReadiness {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskReadiness::Ready => write!(f, "task is ready to be claimed"),
+            TaskReadiness::NotNew { status } => {
+                write!(f, "task can't be claimed in state {status}")
+            }
+            TaskReadiness::MissingFeature { feature } => {
+                write!(f, "task requires a fairy with feature \"{feature}\"")
+            }
+            TaskReadiness::WaitingForDependency { task_id } => {
+                write!(f, "task waits for task {task_id} to finish successfully")
+            }
+            TaskReadiness::Deferred { not_before } => {
+                write!(f, "task is deferred until {}", not_before.to_rfc3339())
+            }
+            TaskReadiness::ActiveLockCollision { lock, held_by } => write!(
+                f,
+                "lock \"{}\" ({}) is held by running {}",
+                lock.name,
+                lock.kind,
+                fmt_task(held_by)
+            ),
+            TaskReadiness::PassiveLockCollision { lock, held_by } => write!(
+                f,
+                "lock \"{}\" ({}) is reserved by pending {}",
+                lock.name,
+                lock.kind,
+                fmt_task(held_by)
+            ),
+            TaskReadiness::HeldBackForWriter { lock, writer } => write!(
+                f,
+                "task is held back for pending {}, which waits for lock \"{}\" ({})",
+                fmt_task(writer),
+                lock.name,
+                lock.kind
+            ),
+            TaskReadiness::PoisonedBy { lock, task_id } => write!(
+                f,
+                "lock \"{}\" is poisoned by failed {}",
+                lock.name,
+                fmt_task(task_id)
+            ),
+            TaskReadiness::GroupLimitReached { group, max_running } => write!(
+                f,
+                "group \"{group}\" already runs its maximum of {max_running} tasks"
+            ),
+        }
+    }
+}
@@ -1,14 +1,91 @@
+use crate::database::entities::lock::lock_names_overlap;
 use crate::database::entities::task::TaskStatus;
 use crate::vicky::constraints::{ConstraintEvaluation, ConstraintFail, Constraints};
+use crate::vicky::readiness::TaskReadiness;
 use crate::{
     database::entities::{Lock, Task},
     errors::SchedulerError,
 };
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Deserialize;
+use std::cmp::{Ordering, Reverse};
+use std::collections::HashMap;
+
+/// How the next task is picked among all ready tasks.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchedulingMode {
+    /// The task with the highest priority, then the oldest one.
+    #[default]
+    Priority,
+    /// A task of the group with the fewest running tasks relative to its weight, so that a
+    /// group with many queued tasks can't block the others. Tasks without a group form a group
+    /// of their own. Within a group, the task is picked by priority and age.
+    FairShare,
+}
+
+/// Scheduling rules from the server config.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct SchedulerConfig {
+    #[serde(default)]
+    pub scheduling: SchedulingMode,
+    /// Limits and weights of task groups, keyed by the group name.
+    #[serde(default)]
+    pub groups: HashMap<String, GroupConfig>,
+    /// Rules of single locks, keyed by the lock name.
+    #[serde(default)]
+    pub locks: HashMap<String, LockConfig>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct GroupConfig {
+    /// How many tasks of the group may run at the same time.
+    pub max_running: Option<usize>,
+    /// Share of the group in fair-share scheduling, defaults to 1.
+    pub weight: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct LockConfig {
+    /// Seconds a WRITE task may wait for the lock, before READ tasks that were queued after it are
+    /// held back until it ran. `0` holds them back as soon as a writer waits.
+    pub writer_preference_sec: Option<u32>,
+}
+
+impl SchedulerConfig {
+    fn group_weight(&self, group: Option<&str>) -> usize {
+        group
+            .and_then(|group| self.groups.get(group))
+            .and_then(|config| config.weight)
+            .map_or(1, |weight| weight.max(1) as usize)
+    }
+
+    /// The writer preference of the configured locks that overlap both the reader and the writer
+    /// lock. If several of them do, the shortest wait applies.
+    fn writer_preference(&self, reader: &Lock, writer: &Lock) -> Option<TimeDelta> {
+        self.locks
+            .iter()
+            .filter(|(name, _)| {
+                lock_names_overlap(name, reader.name()) && lock_names_overlap(name, writer.name())
+            })
+            .filter_map(|(_, config)| config.writer_preference_sec)
+            .min()
+            .map(|seconds| TimeDelta::seconds(i64::from(seconds)))
+    }
+}
+
+impl AsRef<SchedulerConfig> for SchedulerConfig {
+    fn as_ref(&self) -> &SchedulerConfig {
+        self
+    }
+}
 
 pub struct Scheduler<'a> {
     constraints: Constraints<'a>,
     tasks: &'a Vec<Task>,
     machine_features: &'a [String],
+    config: SchedulerConfig,
+    now: DateTime<Utc>,
 }
 
 impl<'a> Scheduler<'a> {
@@ -23,6 +100,8 @@ impl<'a> Scheduler<'a> {
             constraints,
             tasks,
             machine_features,
+            config: SchedulerConfig::default(),
+            now: Utc::now(),
         };
 
         #[cfg(test)]
@@ -44,27 +123,188 @@ impl<'a> Scheduler<'a> {
             .cloned()
     }
 
+    pub fn with_config(mut self, config: SchedulerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    fn find_group_limit(&self, task: &Task) -> Option<ConstraintFail<'a>> {
+        let group = task.group.as_deref()?;
+        let max_running = self.config.groups.get(group)?.max_running?;
+
+        (self.constraints.running_in_group(Some(group)) >= max_running).then(|| {
+            ConstraintFail::GroupLimitReached {
+                group: group.to_string(),
+                max_running,
+            }
+        })
+    }
+
+    /// Whether `writer` could run if it weren't for the locks held by other tasks.
+    fn is_ready_but_for_locks(&self, writer: &Task) -> bool {
+        self.constraints
+            .find_unfinished_dependency(writer)
+            .is_none()
+            && self.find_unsupported_features(writer).is_none()
+            && self.find_group_limit(writer).is_none()
+            && !writer
+                .locks
+                .iter()
+                .any(|lock| self.constraints.is_poisoned(lock))
+    }
+
+    /// Finds the lock of a writer that was queued before `task` and waited long enough to hold
+    /// back `task` from reading the same lock. Writers that couldn't run anyway aren't waiting
+    /// for it.
+    fn find_waiting_writer(&self, task: &Task) -> Option<&'a Lock> {
+        let read_locks: Vec<&Lock> = task
+            .locks
+            .iter()
+            .filter(|lock| lock.kind.is_read())
+            .collect();
+        if read_locks.is_empty() {
+            return None;
+        }
+
+        self.tasks
+            .iter()
+            .filter(|writer| writer.is_new() && writer.created_at < task.created_at)
+            .filter(|writer| self.is_ready_but_for_locks(writer))
+            .find_map(|writer| {
+                let waiting_since = writer.not_before.map_or(writer.created_at, |not_before| {
+                    not_before.max(writer.created_at)
+                });
+
+                writer
+                    .locks
+                    .iter()
+                    .filter(|writer_lock| writer_lock.kind.is_write())
+                    .find(|writer_lock| {
+                        read_locks.iter().any(|lock| {
+                            lock.overlaps(writer_lock)
+                                && self
+                                    .config
+                                    .writer_preference(lock, writer_lock)
+                                    .is_some_and(|preference| {
+                                        self.now - waiting_since >= preference
+                                    })
+                        })
+                    })
+            })
+    }
+
     fn evaluate_task_readiness(&'a self, task: &Task) -> ConstraintEvaluation<'a> {
         if task.status != TaskStatus::New {
             return ConstraintEvaluation::NotReady;
         }
 
+        if let Some(not_before) = task.not_before.filter(|not_before| *not_before > self.now) {
+            return ConstraintEvaluation::deferred(not_before);
+        }
+
+        if let Some(dependency) = self.constraints.find_unfinished_dependency(task) {
+            return ConstraintEvaluation::waiting_for_dependency(dependency);
+        }
+
         if let Some(feature) = self.find_unsupported_features(task) {
             return ConstraintEvaluation::missing_feature(feature);
         }
 
+        if let Some(limit) = self.find_group_limit(task) {
+            return ConstraintEvaluation::Constrained(limit);
+        }
+
         if let Some(constraint) = self.find_constraint(task) {
             return ConstraintEvaluation::Constrained(constraint);
         }
 
+        if let Some(writer_lock) = self.find_waiting_writer(task) {
+            return ConstraintEvaluation::Constrained(ConstraintFail::HeldBackForWriter(
+                writer_lock,
+            ));
+        }
+
         ConstraintEvaluation::Ready
     }
 
+    fn find_lock_owner(&self, lock: &Lock) -> Option<&'a Task> {
+        self.tasks.iter().find(|task| {
+            task.locks
+                .iter()
+                .any(|task_lock| std::ptr::eq(task_lock, lock))
+        })
+    }
+
+    pub fn explain_task_readiness(&'a self, task: &Task) -> TaskReadiness {
+        match self.evaluate_task_readiness(task) {
+            ConstraintEvaluation::Ready => TaskReadiness::Ready,
+            ConstraintEvaluation::NotReady => TaskReadiness::NotNew {
+                status: task.status,
+            },
+            ConstraintEvaluation::Constrained(fail) => match fail {
+                ConstraintFail::UnsupportedFeature(feature) => {
+                    TaskReadiness::MissingFeature { feature }
+                }
+                ConstraintFail::WaitingForDependency(task_id) => {
+                    TaskReadiness::WaitingForDependency { task_id }
+                }
+                ConstraintFail::Deferred(not_before) => TaskReadiness::Deferred { not_before },
+                ConstraintFail::GroupLimitReached { group, max_running } => {
+                    TaskReadiness::GroupLimitReached { group, max_running }
+                }
+                ConstraintFail::ActiveLockCollision(lock) => TaskReadiness::ActiveLockCollision {
+                    lock: lock.clone(),
+                    held_by: self.find_lock_owner(lock).map(|owner| owner.id),
+                },
+                ConstraintFail::PassiveLockCollision(lock) => TaskReadiness::PassiveLockCollision {
+                    lock: lock.clone(),
+                    held_by: self.find_lock_owner(lock).map(|owner| owner.id),
+                },
+                ConstraintFail::HeldBackForWriter(lock) => TaskReadiness::HeldBackForWriter {
+                    lock: lock.clone(),
+                    writer: self.find_lock_owner(lock).map(|owner| owner.id),
+                },
+                ConstraintFail::PoisonedBy(lock) => TaskReadiness::PoisonedBy {
+                    lock: lock.clone(),
+                    task_id: lock.poisoned_by,
+                },
+            },
+        }
+    }
+
+    /// Orders tasks by their share of running tasks in fair-share scheduling, the group with the
+    /// smaller share comes first.
+    fn compare_group_shares(&self, a: &Task, b: &Task) -> Ordering {
+        let share = |task: &Task| {
+            let group = task.group.as_deref();
+            (
+                self.constraints.running_in_group(group),
+                self.config.group_weight(group),
+            )
+        };
+        let ((running_a, weight_a), (running_b, weight_b)) = (share(a), share(b));
+
+        (running_a * weight_b).cmp(&(running_b * weight_a))
+    }
+
+    /// Picks the ready task with the highest priority. Among equal priorities the oldest task wins,
+    /// so that older tasks can't be starved by newer ones. In fair-share scheduling, the group
+    /// is picked first.
     pub fn get_next_task(self) -> Option<Task> {
-        self.tasks
+        let queue_order = |task: &Task| (Reverse(task.priority), task.created_at);
+        let ready_tasks = self
+            .tasks
             .iter()
-            .find(|task| self.evaluate_task_readiness(task).is_ready())
-            .cloned()
+            .filter(|task| self.evaluate_task_readiness(task).is_ready());
+
+        match self.config.scheduling {
+            SchedulingMode::Priority => ready_tasks.min_by_key(|task| queue_order(task)),
+            SchedulingMode::FairShare => ready_tasks.min_by(|a, b| {
+                self.compare_group_shares(a, b)
+                    .then_with(|| queue_order(a).cmp(&queue_order(b)))
+            }),
+        }
+        .cloned()
     }
 
     #[allow(unused)]
@@ -78,11 +318,15 @@ impl<'a> Scheduler<'a> {
 
 #[cfg(test)]
 mod tests {
+    use chrono::{TimeDelta, Utc};
+    use std::collections::HashMap;
     use uuid::Uuid;
 
-    use super::Scheduler;
-    use crate::database::entities::task::{TaskResult, TaskStatus};
+    use super::{GroupConfig, LockConfig, Scheduler, SchedulerConfig, SchedulingMode};
+    use crate::database::entities::task::{RetryPolicy, TaskResult, TaskStatus};
     use crate::database::entities::{Lock, Task};
+    use crate::vicky::constraints::{ConstraintEvaluation, ConstraintFail};
+    use crate::vicky::readiness::TaskReadiness;
 
     #[test]
     fn scheduler_creation_no_constraints() {
@@ -553,4 +797,1124 @@ mod tests {
 
         assert_eq!(res.get_next_task(), None);
     }
+
+    #[test]
+    fn scheduler_prefers_oldest_task_with_same_priority() {
+        let now = Utc::now();
+        // The database hands out tasks newest first
+        let tasks = vec![
+            Task::builder()
+                .display_name("Newest")
+                .created_at(now)
+                .build_expect(),
+            Task::builder()
+                .display_name("Oldest")
+                .created_at(now - TimeDelta::minutes(10))
+                .build_expect(),
+            Task::builder()
+                .display_name("Middle")
+                .created_at(now - TimeDelta::minutes(5))
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task().unwrap().display_name, "Oldest");
+    }
+
+    #[test]
+    fn scheduler_prefers_higher_priority_over_age() {
+        let now = Utc::now();
+        let tasks = vec![
+            Task::builder()
+                .display_name("Urgent")
+                .created_at(now)
+                .priority(10)
+                .build_expect(),
+            Task::builder()
+                .display_name("Old")
+                .created_at(now - TimeDelta::hours(1))
+                .build_expect(),
+            Task::builder()
+                .display_name("Unimportant")
+                .created_at(now - TimeDelta::hours(2))
+                .priority(-5)
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task().unwrap().display_name, "Urgent");
+    }
+
+    #[test]
+    fn scheduler_orders_by_priority_then_age() {
+        let now = Utc::now();
+        let tasks = vec![
+            Task::builder()
+                .display_name("High new")
+                .created_at(now)
+                .priority(5)
+                .build_expect(),
+            Task::builder()
+                .display_name("High old")
+                .created_at(now - TimeDelta::minutes(1))
+                .priority(5)
+                .build_expect(),
+            Task::builder()
+                .display_name("Low oldest")
+                .created_at(now - TimeDelta::minutes(2))
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task().unwrap().display_name, "High old");
+    }
+
+    #[test]
+    fn scheduler_skips_blocked_high_priority_task() {
+        let now = Utc::now();
+        let tasks = vec![
+            Task::builder()
+                .display_name("Running writer")
+                .status(TaskStatus::Running)
+                .write_lock("foo1")
+                .build_expect(),
+            Task::builder()
+                .display_name("Blocked urgent")
+                .created_at(now - TimeDelta::minutes(5))
+                .priority(100)
+                .write_lock("foo1")
+                .build_expect(),
+            Task::builder()
+                .display_name("Unblocked")
+                .created_at(now)
+                .write_lock("foo2")
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task().unwrap().display_name, "Unblocked");
+    }
+
+    #[test]
+    fn scheduler_waits_for_pending_dependency() {
+        let build = Task::builder()
+            .display_name("Build config")
+            .status(TaskStatus::Running)
+            .build_expect();
+        let tasks = vec![
+            Task::builder()
+                .display_name("Deploy")
+                .depends_on(build.id)
+                .build_expect(),
+            build,
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+        let eval = res.evaluate_task_readiness(&res.tasks[0]);
+        assert!(
+            eval.is_waiting_for_dependency(),
+            "Expected deploy to wait for the build, got {eval:?}"
+        );
+        assert_eq!(res.get_next_task(), None);
+    }
+
+    #[test]
+    fn scheduler_runs_task_after_dependency_succeeded() {
+        let build = Task::builder()
+            .display_name("Build config")
+            .status(TaskStatus::Finished(TaskResult::Success))
+            .build_expect();
+        let tasks = vec![
+            Task::builder()
+                .display_name("Deploy")
+                .depends_on(build.id)
+                .build_expect(),
+            build,
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task().unwrap().display_name, "Deploy");
+    }
+
+    #[test]
+    fn scheduler_never_runs_task_with_failed_dependency() {
+        let build = Task::builder()
+            .display_name("Build config")
+            .status(TaskStatus::Finished(TaskResult::Error))
+            .build_expect();
+        let tasks = vec![
+            Task::builder()
+                .display_name("Deploy")
+                .depends_on(build.id)
+                .build_expect(),
+            build,
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task(), None);
+    }
+
+    #[test]
+    fn scheduler_treats_unknown_dependency_as_finished() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Deploy")
+                .depends_on(Uuid::new_v4())
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task().unwrap().display_name, "Deploy");
+    }
+
+    #[test]
+    fn scheduler_runs_dependency_before_dependent() {
+        let now = Utc::now();
+        let build = Task::builder()
+            .display_name("Build config")
+            .created_at(now - TimeDelta::minutes(1))
+            .build_expect();
+        let tasks = vec![
+            Task::builder()
+                .display_name("Deploy")
+                .created_at(now - TimeDelta::minutes(2))
+                .priority(10)
+                .depends_on(build.id)
+                .build_expect(),
+            build,
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task().unwrap().display_name, "Build config");
+    }
+
+    #[test]
+    fn scheduler_parent_lock_blocks_child_lock() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Whole prod")
+                .status(TaskStatus::Running)
+                .write_lock("prod")
+                .build_expect(),
+            Task::builder()
+                .display_name("Router 1")
+                .write_lock("prod/router1")
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+        let eval = res.evaluate_task_readiness(&res.tasks[1]);
+        assert!(
+            eval.is_active_collision(),
+            "Expected child lock to collide with running parent lock, got {eval:?}"
+        );
+        assert_eq!(res.get_next_task(), None);
+    }
+
+    #[test]
+    fn scheduler_child_lock_blocks_parent_lock() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Router 1")
+                .status(TaskStatus::Running)
+                .read_lock("prod/router1")
+                .build_expect(),
+            Task::builder()
+                .display_name("Whole prod")
+                .write_lock("prod")
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+        let eval = res.evaluate_task_readiness(&res.tasks[1]);
+        assert!(
+            eval.is_active_collision(),
+            "Expected parent lock to collide with running child lock, got {eval:?}"
+        );
+        assert_eq!(res.get_next_task(), None);
+    }
+
+    #[test]
+    fn scheduler_parent_and_child_read_locks_are_shared() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Whole prod")
+                .status(TaskStatus::Running)
+                .read_lock("prod")
+                .build_expect(),
+            Task::builder()
+                .display_name("Router 1")
+                .read_lock("prod/router1")
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task().unwrap().display_name, "Router 1");
+    }
+
+    #[test]
+    fn scheduler_sibling_locks_dont_conflict() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Router 1")
+                .status(TaskStatus::Running)
+                .write_lock("prod/router1")
+                .build_expect(),
+            Task::builder()
+                .display_name("Router 2")
+                .write_lock("prod/router2")
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task().unwrap().display_name, "Router 2");
+    }
+
+    #[test]
+    fn scheduler_name_prefix_without_separator_is_no_parent() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Prod")
+                .status(TaskStatus::Running)
+                .write_lock("prod")
+                .build_expect(),
+            Task::builder()
+                .display_name("Production")
+                .write_lock("production")
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task().unwrap().display_name, "Production");
+    }
+
+    #[test]
+    fn scheduler_creation_running_parent_and_child_write_fails() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Whole prod")
+                .status(TaskStatus::Running)
+                .write_lock("prod")
+                .build_expect(),
+            Task::builder()
+                .display_name("Router 1")
+                .status(TaskStatus::Running)
+                .write_lock("prod/router1")
+                .build_expect(),
+        ];
+
+        assert!(Scheduler::new(&tasks, &[], &[]).is_err());
+    }
+
+    #[test]
+    fn scheduler_needs_validation_parent_lock_blocks_child() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Validation writer")
+                .status(TaskStatus::NeedsUserValidation)
+                .write_lock("prod")
+                .build_expect(),
+            Task::builder()
+                .display_name("Router 1 reader")
+                .read_lock("prod/router1")
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+        let eval = res.evaluate_task_readiness(&res.tasks[1]);
+        assert!(
+            eval.is_passive_collision(),
+            "Expected passive collision with validation parent lock, got {eval:?}"
+        );
+    }
+
+    #[test]
+    fn scheduler_cleanup_of_parent_waits_for_child() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Cleanup prod")
+                .clean_lock("prod")
+                .build_expect(),
+            Task::builder()
+                .display_name("Router 1")
+                .read_lock("prod/router1")
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task().unwrap().display_name, "Router 1");
+    }
+
+    #[test]
+    fn schedule_with_poisoned_child_lock() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Whole prod")
+                .read_lock("prod")
+                .build_expect(),
+            Task::builder()
+                .display_name("Router 2")
+                .write_lock("prod/router2")
+                .build_expect(),
+        ];
+        let mut poisoned_lock = Lock::write("prod/router1");
+        poisoned_lock.poison(&Uuid::new_v4());
+        let poisoned_locks = vec![poisoned_lock];
+
+        let res = Scheduler::new(&tasks, &poisoned_locks, &[]).unwrap();
+        let eval = res.evaluate_task_readiness(&res.tasks[0]);
+        assert!(
+            matches!(
+                eval,
+                ConstraintEvaluation::Constrained(ConstraintFail::PoisonedBy(_))
+            ),
+            "Expected parent lock to be poisoned by child, got {eval:?}"
+        );
+
+        assert_eq!(res.get_next_task().unwrap().display_name, "Router 2");
+    }
+
+    #[test]
+    fn task_with_parent_and_child_write_lock_conflicts() {
+        let task = Task::builder()
+            .write_lock("prod")
+            .read_lock("prod/router1")
+            .build();
+
+        assert!(task.is_err());
+    }
+
+    fn running_semaphore_holders(count: usize) -> Vec<Task> {
+        (0..count)
+            .map(|i| {
+                Task::builder()
+                    .display_name(format!("Reboot router {i}"))
+                    .status(TaskStatus::Running)
+                    .semaphore_lock("region/wob", 3)
+                    .build_expect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scheduler_semaphore_allows_holders_up_to_capacity() {
+        let mut tasks = running_semaphore_holders(2);
+        tasks.push(
+            Task::builder()
+                .display_name("Reboot router 3")
+                .semaphore_lock("region/wob", 3)
+                .build_expect(),
+        );
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task().unwrap().display_name, "Reboot router 3");
+    }
+
+    #[test]
+    fn scheduler_semaphore_blocks_when_full() {
+        let mut tasks = running_semaphore_holders(3);
+        tasks.push(
+            Task::builder()
+                .display_name("Reboot router 4")
+                .semaphore_lock("region/wob", 3)
+                .build_expect(),
+        );
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+        let eval = res.evaluate_task_readiness(&res.tasks[3]);
+        assert!(
+            eval.is_active_collision(),
+            "Expected full semaphore to collide, got {eval:?}"
+        );
+        assert_eq!(res.get_next_task(), None);
+    }
+
+    #[test]
+    fn scheduler_creation_semaphore_over_capacity_fails() {
+        let tasks = running_semaphore_holders(4);
+
+        assert!(Scheduler::new(&tasks, &[], &[]).is_err());
+    }
+
+    #[test]
+    fn scheduler_semaphore_excludes_writers_and_readers() {
+        let mut tasks = running_semaphore_holders(1);
+        tasks.push(
+            Task::builder()
+                .display_name("Writer")
+                .write_lock("region/wob")
+                .build_expect(),
+        );
+        tasks.push(
+            Task::builder()
+                .display_name("Reader")
+                .read_lock("region/wob")
+                .build_expect(),
+        );
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task(), None);
+    }
+
+    #[test]
+    fn scheduler_semaphore_waits_for_running_writer() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Writer")
+                .status(TaskStatus::Running)
+                .write_lock("region/wob")
+                .build_expect(),
+            Task::builder()
+                .display_name("Reboot router")
+                .semaphore_lock("region/wob", 3)
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task(), None);
+    }
+
+    #[test]
+    fn scheduler_semaphore_excludes_parent_and_child_holders() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Reboot router 1")
+                .status(TaskStatus::Running)
+                .semaphore_lock("region/wob/router1", 3)
+                .build_expect(),
+            Task::builder()
+                .display_name("Reboot any router")
+                .semaphore_lock("region/wob", 3)
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+        let eval = res.evaluate_task_readiness(&res.tasks[1]);
+        assert!(
+            eval.is_active_collision(),
+            "Expected parent semaphore to collide with child holder, got {eval:?}"
+        );
+        assert_eq!(res.get_next_task(), None);
+    }
+
+    #[test]
+    fn scheduler_semaphore_uses_smallest_declared_capacity() {
+        let holder = Task::builder()
+            .display_name("Reboot router 1")
+            .status(TaskStatus::Running)
+            .semaphore_lock("region/wob", 1)
+            .build_expect();
+        let candidate = Task::builder()
+            .display_name("Reboot router 2")
+            .semaphore_lock("region/wob", 3)
+            .build_expect();
+
+        let tasks = vec![holder.clone(), candidate];
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+        assert_eq!(res.get_next_task(), None);
+
+        let holder = Task {
+            locks: vec![Lock::semaphore("region/wob", 3)],
+            ..holder
+        };
+        let candidate = Task {
+            locks: vec![Lock::semaphore("region/wob", 1)],
+            ..tasks[1].clone()
+        };
+        let tasks = vec![holder, candidate];
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+        assert_eq!(res.get_next_task(), None);
+    }
+
+    #[test]
+    fn scheduler_explains_active_collision_with_holder() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Writer")
+                .status(TaskStatus::Running)
+                .write_lock("prod")
+                .build_expect(),
+            Task::builder()
+                .display_name("Reader")
+                .read_lock("prod")
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(
+            res.explain_task_readiness(&tasks[1]),
+            TaskReadiness::ActiveLockCollision {
+                lock: Lock::write("prod"),
+                held_by: Some(tasks[0].id),
+            }
+        );
+    }
+
+    #[test]
+    fn scheduler_explains_passive_collision_with_holder() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Validation writer")
+                .status(TaskStatus::NeedsUserValidation)
+                .write_lock("prod")
+                .build_expect(),
+            Task::builder()
+                .display_name("Reader")
+                .read_lock("prod")
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(
+            res.explain_task_readiness(&tasks[1]),
+            TaskReadiness::PassiveLockCollision {
+                lock: Lock::write("prod"),
+                held_by: Some(tasks[0].id),
+            }
+        );
+    }
+
+    #[test]
+    fn scheduler_explains_poisoned_lock() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Writer")
+                .write_lock("prod")
+                .build_expect(),
+        ];
+        let poisoner = Uuid::new_v4();
+        let mut poisoned_lock = Lock::write("prod");
+        poisoned_lock.poison(&poisoner);
+        let poisoned_locks = vec![poisoned_lock.clone()];
+
+        let res = Scheduler::new(&tasks, &poisoned_locks, &[]).unwrap();
+
+        assert_eq!(
+            res.explain_task_readiness(&tasks[0]),
+            TaskReadiness::PoisonedBy {
+                lock: poisoned_lock,
+                task_id: Some(poisoner),
+            }
+        );
+    }
+
+    #[test]
+    fn scheduler_explains_missing_feature_and_state() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Needs GPU")
+                .requires_feature("gpu")
+                .build_expect(),
+            Task::builder()
+                .display_name("Running")
+                .status(TaskStatus::Running)
+                .build_expect(),
+            Task::builder().display_name("Ready").build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(
+            res.explain_task_readiness(&tasks[0]),
+            TaskReadiness::MissingFeature {
+                feature: "gpu".to_string()
+            }
+        );
+        assert_eq!(
+            res.explain_task_readiness(&tasks[1]),
+            TaskReadiness::NotNew {
+                status: TaskStatus::Running
+            }
+        );
+        assert_eq!(res.explain_task_readiness(&tasks[2]), TaskReadiness::Ready);
+    }
+
+    fn group_limits(group: &str, max_running: usize) -> SchedulerConfig {
+        SchedulerConfig {
+            groups: HashMap::from([(
+                group.to_string(),
+                GroupConfig {
+                    max_running: Some(max_running),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn scheduler_holds_back_group_at_its_limit() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Core router 1")
+                .group("core-routers")
+                .status(TaskStatus::Running)
+                .build_expect(),
+            Task::builder()
+                .display_name("Core router 2")
+                .group("core-routers")
+                .status(TaskStatus::Running)
+                .build_expect(),
+            Task::builder()
+                .display_name("Core router 3")
+                .group("core-routers")
+                .priority(10)
+                .build_expect(),
+            Task::builder()
+                .display_name("Access switch")
+                .group("access-switches")
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[])
+            .unwrap()
+            .with_config(group_limits("core-routers", 2));
+
+        assert_eq!(
+            res.explain_task_readiness(&tasks[2]),
+            TaskReadiness::GroupLimitReached {
+                group: "core-routers".to_string(),
+                max_running: 2,
+            }
+        );
+        assert_eq!(res.get_next_task().map(|t| t.id), Some(tasks[3].id));
+    }
+
+    #[test]
+    fn scheduler_runs_group_below_its_limit() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Core router 1")
+                .group("core-routers")
+                .status(TaskStatus::Running)
+                .build_expect(),
+            Task::builder()
+                .display_name("Core router 2")
+                .group("core-routers")
+                .build_expect(),
+        ];
+
+        let limited = Scheduler::new(&tasks, &[], &[])
+            .unwrap()
+            .with_config(group_limits("core-routers", 2));
+        assert_eq!(limited.get_next_task().map(|t| t.id), Some(tasks[1].id));
+
+        let unlimited = Scheduler::new(&tasks, &[], &[])
+            .unwrap()
+            .with_config(group_limits("access-switches", 1));
+        assert_eq!(unlimited.get_next_task().map(|t| t.id), Some(tasks[1].id));
+    }
+
+    fn queued_group_tasks(group: &str, count: usize) -> Vec<Task> {
+        (0..count)
+            .map(|i| {
+                Task::builder()
+                    .display_name(format!("{group} {i}"))
+                    .group(group)
+                    .build_expect()
+            })
+            .collect()
+    }
+
+    /// Claims tasks one after another, like fairies would, and returns the groups of the claimed tasks.
+    fn claim_groups(tasks: &mut [Task], config: &SchedulerConfig, claims: usize) -> Vec<String> {
+        let mut groups = vec![];
+        for _ in 0..claims {
+            let snapshot = tasks.to_vec();
+            let res = Scheduler::new(&snapshot, &[], &[])
+                .unwrap()
+                .with_config(config.clone());
+            let Some(next) = res.get_next_task() else {
+                break;
+            };
+
+            let claimed = tasks.iter_mut().find(|task| task.id == next.id).unwrap();
+            claimed.status = TaskStatus::Running;
+            groups.extend(claimed.group.clone());
+        }
+        groups
+    }
+
+    #[test]
+    fn scheduler_fair_share_interleaves_groups() {
+        // the bulk group queued all of its tasks before the team group
+        let mut tasks = queued_group_tasks("bulk", 6);
+        tasks.extend(queued_group_tasks("team", 2));
+
+        let priority = SchedulerConfig::default();
+        assert_eq!(
+            claim_groups(&mut tasks.clone(), &priority, 4),
+            ["bulk", "bulk", "bulk", "bulk"]
+        );
+
+        let fair_share = SchedulerConfig {
+            scheduling: SchedulingMode::FairShare,
+            ..Default::default()
+        };
+        assert_eq!(
+            claim_groups(&mut tasks, &fair_share, 6),
+            ["bulk", "team", "bulk", "team", "bulk", "bulk"]
+        );
+    }
+
+    #[test]
+    fn scheduler_fair_share_follows_group_weights() {
+        let mut tasks = queued_group_tasks("team", 6);
+        tasks.extend(queued_group_tasks("bulk", 6));
+
+        let config = SchedulerConfig {
+            scheduling: SchedulingMode::FairShare,
+            groups: HashMap::from([(
+                "bulk".to_string(),
+                GroupConfig {
+                    weight: Some(2),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        let groups = claim_groups(&mut tasks, &config, 6);
+
+        let bulk_claims = groups.iter().filter(|group| *group == "bulk").count();
+        assert_eq!((bulk_claims, groups.len() - bulk_claims), (4, 2));
+    }
+
+    fn writer_preference(lock_name: &str, seconds: u32) -> SchedulerConfig {
+        SchedulerConfig {
+            locks: HashMap::from([(
+                lock_name.to_string(),
+                LockConfig {
+                    writer_preference_sec: Some(seconds),
+                },
+            )]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn scheduler_holds_back_readers_behind_waiting_writer() {
+        let now = Utc::now();
+        let tasks = vec![
+            Task::builder()
+                .display_name("Running reader")
+                .status(TaskStatus::Running)
+                .read_lock("prod")
+                .build_expect(),
+            Task::builder()
+                .display_name("Writer")
+                .created_at(now - TimeDelta::minutes(10))
+                .write_lock("prod")
+                .build_expect(),
+            Task::builder()
+                .display_name("Later reader")
+                .created_at(now - TimeDelta::minutes(5))
+                .read_lock("prod")
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+        assert_eq!(res.get_next_task().map(|t| t.id), Some(tasks[2].id));
+
+        let res = Scheduler::new(&tasks, &[], &[])
+            .unwrap()
+            .with_config(writer_preference("prod", 300));
+        assert_eq!(
+            res.explain_task_readiness(&tasks[2]),
+            TaskReadiness::HeldBackForWriter {
+                lock: Lock::write("prod"),
+                writer: Some(tasks[1].id),
+            }
+        );
+        assert_eq!(res.get_next_task(), None);
+    }
+
+    #[test]
+    fn scheduler_writer_preference_only_holds_back_later_readers_after_the_wait() {
+        let now = Utc::now();
+        let tasks = vec![
+            Task::builder()
+                .display_name("Running reader")
+                .status(TaskStatus::Running)
+                .read_lock("prod")
+                .build_expect(),
+            Task::builder()
+                .display_name("Earlier reader")
+                .created_at(now - TimeDelta::minutes(20))
+                .priority(-1)
+                .read_lock("prod")
+                .build_expect(),
+            Task::builder()
+                .display_name("Writer")
+                .created_at(now - TimeDelta::minutes(10))
+                .write_lock("prod")
+                .build_expect(),
+            Task::builder()
+                .display_name("Later reader")
+                .created_at(now - TimeDelta::minutes(5))
+                .read_lock("prod")
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[])
+            .unwrap()
+            .with_config(writer_preference("prod", 3600));
+        assert_eq!(res.get_next_task().map(|t| t.id), Some(tasks[3].id));
+
+        let res = Scheduler::new(&tasks, &[], &[])
+            .unwrap()
+            .with_config(writer_preference("prod", 0));
+        assert_eq!(
+            res.explain_task_readiness(&tasks[3]),
+            TaskReadiness::HeldBackForWriter {
+                lock: Lock::write("prod"),
+                writer: Some(tasks[2].id),
+            }
+        );
+        assert_eq!(res.get_next_task().map(|t| t.id), Some(tasks[1].id));
+    }
+
+    #[test]
+    fn scheduler_writer_preference_of_parent_lock_holds_back_child_readers() {
+        let now = Utc::now();
+        let tasks = vec![
+            Task::builder()
+                .display_name("Running reader")
+                .status(TaskStatus::Running)
+                .read_lock("prod/router1")
+                .build_expect(),
+            Task::builder()
+                .display_name("Writer")
+                .created_at(now - TimeDelta::minutes(10))
+                .write_lock("prod/router1")
+                .build_expect(),
+            Task::builder()
+                .display_name("Later reader")
+                .created_at(now - TimeDelta::minutes(5))
+                .read_lock("prod/router1")
+                .build_expect(),
+            Task::builder()
+                .display_name("Sibling reader")
+                .created_at(now - TimeDelta::minutes(5))
+                .read_lock("prod/router2")
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[])
+            .unwrap()
+            .with_config(writer_preference("prod", 0));
+        assert_eq!(
+            res.explain_task_readiness(&tasks[2]),
+            TaskReadiness::HeldBackForWriter {
+                lock: Lock::write("prod/router1"),
+                writer: Some(tasks[1].id),
+            }
+        );
+        assert_eq!(res.get_next_task().map(|t| t.id), Some(tasks[3].id));
+    }
+
+    #[test]
+    fn scheduler_writer_that_cannot_run_does_not_hold_back_readers() {
+        let now = Utc::now();
+        let writer = |name: &str| {
+            Task::builder()
+                .display_name(name)
+                .created_at(now - TimeDelta::minutes(10))
+                .write_lock("prod")
+        };
+        let mut poisoned = Lock::write("dns");
+        poisoned.poison(&Uuid::new_v4());
+        let tasks = vec![
+            Task::builder()
+                .display_name("Running reader")
+                .status(TaskStatus::Running)
+                .group("deploy")
+                .read_lock("prod")
+                .build_expect(),
+            writer("Writer without fairy")
+                .requires_feature("bgp")
+                .build_expect(),
+            writer("Writer over group limit")
+                .group("deploy")
+                .build_expect(),
+            writer("Writer with poisoned lock")
+                .write_lock("dns")
+                .build_expect(),
+            Task::builder()
+                .display_name("Later reader")
+                .created_at(now - TimeDelta::minutes(5))
+                .read_lock("prod")
+                .build_expect(),
+        ];
+        let config = SchedulerConfig {
+            groups: HashMap::from([(
+                "deploy".to_string(),
+                GroupConfig {
+                    max_running: Some(1),
+                    weight: None,
+                },
+            )]),
+            ..writer_preference("prod", 0)
+        };
+
+        let res = Scheduler::new(&tasks, std::slice::from_ref(&poisoned), &[])
+            .unwrap()
+            .with_config(config);
+        assert_eq!(res.explain_task_readiness(&tasks[4]), TaskReadiness::Ready);
+        assert_eq!(res.get_next_task().map(|t| t.id), Some(tasks[4].id));
+    }
+
+    #[test]
+    fn scheduler_defers_task_until_not_before() {
+        let not_before = Utc::now() + TimeDelta::hours(2);
+        let tasks = vec![
+            Task::builder()
+                .display_name("Maintenance window")
+                .not_before(not_before)
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+        let eval = res.evaluate_task_readiness(&res.tasks[0]);
+        assert!(eval.is_deferred(), "Expected a deferred task, got {eval:?}");
+        assert_eq!(
+            res.explain_task_readiness(&tasks[0]),
+            TaskReadiness::Deferred { not_before }
+        );
+        assert_eq!(res.get_next_task(), None);
+    }
+
+    #[test]
+    fn scheduler_runs_task_after_not_before_passed() {
+        let tasks = vec![
+            Task::builder()
+                .display_name("Maintenance window")
+                .not_before(Utc::now() - TimeDelta::minutes(1))
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(
+            res.get_next_task().unwrap().display_name,
+            "Maintenance window"
+        );
+    }
+
+    #[test]
+    fn scheduler_skips_deferred_task_in_favor_of_newer_one() {
+        let now = Utc::now();
+        let tasks = vec![
+            Task::builder()
+                .display_name("Deferred")
+                .created_at(now - TimeDelta::minutes(2))
+                .priority(10)
+                .not_before(now + TimeDelta::hours(1))
+                .write_lock("foo")
+                .build_expect(),
+            Task::builder()
+                .display_name("Immediate")
+                .created_at(now - TimeDelta::minutes(1))
+                .write_lock("foo")
+                .build_expect(),
+        ];
+
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+
+        assert_eq!(res.get_next_task().unwrap().display_name, "Immediate");
+    }
+
+    #[test]
+    fn scheduler_defers_retry_by_backoff() {
+        let mut failed = Task::builder()
+            .display_name("Flaky fetch")
+            .status(TaskStatus::Running)
+            .write_lock("foo")
+            .retry_policy(RetryPolicy {
+                max_attempts: 3,
+                backoff_sec: 60,
+                retry_on: vec![TaskResult::Error, TaskResult::Timeout],
+            })
+            .build_expect();
+        failed.finish(TaskResult::Error);
+        assert!(
+            failed.locks.iter().all(|lock| !lock.is_poisoned()),
+            "a task that will be retried must not poison its locks"
+        );
+
+        let retry = failed.next_attempt(TaskResult::Error).unwrap();
+        assert_eq!(retry.attempt, 2);
+        assert_eq!(retry.retry_of, Some(failed.id));
+        assert!(retry.not_before.unwrap() > Utc::now() + TimeDelta::seconds(50));
+
+        let tasks = vec![failed, retry];
+        let res = Scheduler::new(&tasks, &[], &[]).unwrap();
+        assert!(res.evaluate_task_readiness(&res.tasks[1]).is_deferred());
+    }
+
+    #[test]
+    fn retry_backoff_grows_exponentially_until_attempts_run_out() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            backoff_sec: 10,
+            retry_on: vec![TaskResult::Timeout],
+        };
+
+        assert_eq!(policy.backoff(1), TimeDelta::seconds(10));
+        assert_eq!(policy.backoff(2), TimeDelta::seconds(20));
+        assert!(policy.retries(TaskResult::Timeout, 2));
+        assert!(!policy.retries(TaskResult::Timeout, 3));
+        assert!(!policy.retries(TaskResult::Error, 1));
+        assert!(!policy.retries(TaskResult::Cancel, 1));
+    }
+
+    #[test]
+    fn rerun_keeps_spec_and_waits_for_poisoned_lock() {
+        let mut failed = Task::builder()
+            .display_name("Deploy router")
+            .status(TaskStatus::Running)
+            .write_lock("router")
+            .requires_feature("big_cpu")
+            .group("deployments")
+            .needs_confirmation(true)
+            .build_expect();
+        failed.finish(TaskResult::Error);
+
+        let rerun = failed.rerun().unwrap();
+        assert_ne!(rerun.id, failed.id);
+        assert_eq!(rerun.rerun_of, Some(failed.id));
+        assert_eq!(rerun.status, TaskStatus::NeedsUserValidation);
+        assert_eq!(rerun.flake_ref, failed.flake_ref);
+        assert_eq!(rerun.features, failed.features);
+        assert_eq!(rerun.group, failed.group);
+        assert!(rerun.locks.iter().all(|lock| !lock.is_poisoned()));
+
+        let mut rerun = rerun;
+        rerun.status = TaskStatus::New;
+        let poisoned_locks = failed.locks.clone();
+        let features = vec!["big_cpu".to_string()];
+        let tasks = vec![failed, rerun];
+
+        let res = Scheduler::new(&tasks, &poisoned_locks, &features).unwrap();
+        assert_eq!(res.get_next_task(), None);
+
+        let res = Scheduler::new(&tasks, &[], &features).unwrap();
+        assert_eq!(res.get_next_task().unwrap().id, tasks[1].id);
+    }
 }
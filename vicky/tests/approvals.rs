@@ -0,0 +1,196 @@
+mod common;
+
+use diesel::PgConnection;
+use std::sync::Barrier;
+use std::thread;
+use uuid::Uuid;
+use vickylib::database::entities::Task;
+use vickylib::database::entities::task::TaskStatus;
+use vickylib::database::entities::task::db_impl::TaskDatabase;
+use vickylib::database::entities::task_approval::ApprovalOutcome;
+use vickylib::database::entities::task_approval::db_impl::TaskApprovalDatabase;
+use vickylib::database::entities::task_edit::TaskEdit;
+use vickylib::database::entities::task_edit::db_impl::TaskEditDatabase;
+use vickylib::database::entities::task_event::Actor;
+use vickylib::database::entities::task_event::db_impl::TaskEventDatabase;
+use vickylib::database::entities::user::db_impl::UserDatabase;
+use vickylib::database::entities::user::{Role, User};
+
+fn user(conn: &mut PgConnection, name: &str) -> Uuid {
+    let user = User {
+        id: Uuid::new_v4(),
+        name: name.to_string(),
+        role: Role::Admin,
+    };
+    let id = user.id;
+    conn.upsert_user(user).unwrap();
+    id
+}
+
+fn task_requiring_approvals(conn: &mut PgConnection, required_approvals: u32) -> Task {
+    task_created_by(conn, required_approvals, None)
+}
+
+fn task_created_by(
+    conn: &mut PgConnection,
+    required_approvals: u32,
+    created_by: Option<Uuid>,
+) -> Task {
+    let task = Task::builder()
+        .display_name("Deploy core router")
+        .status(TaskStatus::NeedsUserValidation)
+        .needs_confirmation(true)
+        .required_approvals(required_approvals)
+        .maybe_created_by(created_by)
+        // keeps confirmed tasks from being claimed by other tests
+        .requires_features(vec![Uuid::new_v4().to_string()])
+        .build()
+        .expect("task should be valid");
+    let task_id = task.id;
+    conn.put_task(task).unwrap();
+    conn.get_task(task_id).unwrap().unwrap()
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn task_is_confirmed_by_enough_distinct_approvals() {
+    common::test_transaction(|conn| {
+        let alice = user(conn, "alice");
+        let bob = user(conn, "bob");
+        let task = task_requiring_approvals(conn, 2);
+
+        assert_eq!(
+            conn.approve_task(&task, alice).unwrap(),
+            ApprovalOutcome::Recorded
+        );
+        assert_eq!(
+            conn.approve_task(&task, alice).unwrap(),
+            ApprovalOutcome::Recorded,
+            "approving twice doesn't count twice"
+        );
+        assert_eq!(
+            conn.get_task(task.id).unwrap().unwrap().status,
+            TaskStatus::NeedsUserValidation
+        );
+
+        assert_eq!(
+            conn.approve_task(&task, bob).unwrap(),
+            ApprovalOutcome::Confirmed
+        );
+        assert_eq!(
+            conn.get_task(task.id).unwrap().unwrap().status,
+            TaskStatus::New
+        );
+
+        let approvals = conn.get_task_approvals(task.id).unwrap();
+        let approvers: Vec<Uuid> = approvals.iter().map(|a| a.user_id).collect();
+        assert_eq!(approvers, vec![alice, bob]);
+    });
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn editing_a_task_discards_its_approvals() {
+    common::test_transaction(|conn| {
+        let alice = user(conn, "alice");
+        let mut task = task_requiring_approvals(conn, 2);
+        conn.approve_task(&task, alice).unwrap();
+
+        let mut edit = TaskEdit::new(task.id, None);
+        edit.record("priority", &mut task.priority, Some(10));
+        assert_eq!(conn.edit_task(&task, &edit).unwrap(), 1);
+
+        assert!(conn.get_task_approvals(task.id).unwrap().is_empty());
+    });
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn creator_cannot_approve_their_task() {
+    common::test_transaction(|conn| {
+        let alice = user(conn, "alice");
+        let task = task_created_by(conn, 1, Some(alice));
+
+        assert_eq!(
+            conn.approve_task(&task, alice).unwrap(),
+            ApprovalOutcome::Forbidden
+        );
+        assert!(conn.get_task_approvals(task.id).unwrap().is_empty());
+        assert_eq!(
+            conn.get_task(task.id).unwrap().unwrap().status,
+            TaskStatus::NeedsUserValidation
+        );
+    });
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn editor_cannot_approve_the_edited_task() {
+    common::test_transaction(|conn| {
+        let alice = user(conn, "alice");
+        let bob = user(conn, "bob");
+        let mut task = task_requiring_approvals(conn, 1);
+
+        let mut edit = TaskEdit::new(task.id, Some(alice));
+        edit.record("priority", &mut task.priority, Some(10));
+        assert_eq!(conn.edit_task(&task, &edit).unwrap(), 1);
+
+        assert_eq!(
+            conn.approve_task(&task, alice).unwrap(),
+            ApprovalOutcome::Forbidden
+        );
+        assert_eq!(
+            conn.approve_task(&task, bob).unwrap(),
+            ApprovalOutcome::Confirmed
+        );
+    });
+}
+
+/// The approvals need their own connections, so this test commits its tasks and cancels them in
+/// the end.
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn concurrent_approvals_confirm_the_task_once() {
+    let mut conn = common::connect();
+    let approvers = [user(&mut conn, "alice"), user(&mut conn, "bob")];
+
+    for _ in 0..10 {
+        let task = task_requiring_approvals(&mut conn, 2);
+        let barrier = Barrier::new(approvers.len());
+
+        let outcomes: Vec<ApprovalOutcome> = thread::scope(|scope| {
+            let approvals: Vec<_> = approvers
+                .iter()
+                .map(|&approver| {
+                    let (task, barrier) = (&task, &barrier);
+                    scope.spawn(move || {
+                        let mut conn = common::connect();
+                        barrier.wait();
+                        conn.approve_task(task, approver).unwrap()
+                    })
+                })
+                .collect();
+
+            approvals
+                .into_iter()
+                .map(|approval| approval.join().unwrap())
+                .collect()
+        });
+
+        assert_eq!(
+            outcomes
+                .iter()
+                .filter(|&&outcome| outcome == ApprovalOutcome::Confirmed)
+                .count(),
+            1,
+            "exactly one of the approvals confirms the task"
+        );
+        assert_eq!(
+            conn.get_task(task.id).unwrap().unwrap().status,
+            TaskStatus::New
+        );
+        assert_eq!(conn.get_task_events(task.id).unwrap().len(), 1);
+
+        conn.cancel_task(task.id, false, &Actor::System).unwrap();
+    }
+}
@@ -0,0 +1,277 @@
+mod common;
+
+use chrono::Utc;
+use diesel::PgConnection;
+use uuid::Uuid;
+use vickylib::database::entities::Task;
+use vickylib::database::entities::lock::db_impl::LockDatabase;
+use vickylib::database::entities::task::db_impl::TaskDatabase;
+use vickylib::database::entities::task::{TaskRejection, TaskResult, TaskStatus};
+use vickylib::database::entities::task_event::Actor;
+use vickylib::database::entities::task_event::db_impl::TaskEventDatabase;
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn running_task_is_cancelled_on_request() {
+    common::test_transaction(|conn| {
+        let lock_name = format!("{}/router", Uuid::new_v4());
+        let running = Task::builder()
+            .display_name("Runaway deployment")
+            .write_lock(&lock_name)
+            .status(TaskStatus::Running)
+            .claimed_at(Utc::now())
+            .build()
+            .expect("task should be valid");
+        let dependent = Task::builder()
+            .display_name("Verify")
+            .depends_on(running.id)
+            .build()
+            .expect("task should be valid");
+        let (running_id, dependent_id) = (running.id, dependent.id);
+        conn.put_task(running).unwrap();
+        conn.put_task(dependent).unwrap();
+
+        assert_eq!(
+            conn.request_task_cancellation(running_id, &Actor::System)
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            conn.request_task_cancellation(running_id, &Actor::System)
+                .unwrap(),
+            0,
+            "the first request is kept"
+        );
+        let requested = conn.get_task(running_id).unwrap().unwrap();
+        assert_eq!(requested.status, TaskStatus::Running);
+        assert!(requested.cancel_requested_at.is_some());
+
+        assert_eq!(
+            conn.cancel_task(running_id, false, &Actor::System).unwrap(),
+            1
+        );
+        assert_eq!(
+            conn.cancel_task(running_id, false, &Actor::System).unwrap(),
+            0
+        );
+
+        let cancelled = conn.get_task(running_id).unwrap().unwrap();
+        assert_eq!(cancelled.status, TaskStatus::Finished(TaskResult::Cancel));
+        assert!(cancelled.finished_at.is_some());
+        assert!(
+            !conn
+                .get_poisoned_locks()
+                .unwrap()
+                .iter()
+                .any(|lock| lock.name == lock_name),
+            "locks stay clean unless poisoning on cancel is enabled"
+        );
+        assert_eq!(
+            conn.get_task(dependent_id).unwrap().unwrap().status,
+            TaskStatus::Finished(TaskResult::DependencyFailed)
+        );
+        assert_eq!(
+            conn.request_task_cancellation(running_id, &Actor::System)
+                .unwrap(),
+            0,
+            "finished tasks can't be cancelled"
+        );
+
+        let history: Vec<_> = conn
+            .get_task_events(running_id)
+            .unwrap()
+            .into_iter()
+            .map(|event| (event.to_status, event.reason))
+            .collect();
+        assert_eq!(
+            history,
+            [
+                (
+                    TaskStatus::Running,
+                    Some("cancellation requested".to_string())
+                ),
+                (
+                    TaskStatus::Finished(TaskResult::Cancel),
+                    Some("the cancellation was requested".to_string())
+                ),
+            ],
+            "only the changes that happened are recorded"
+        );
+    });
+}
+
+fn running_task(conn: &mut PgConnection, lock_name: &str) -> Task {
+    let task = Task::builder()
+        .display_name("Runaway deployment")
+        .write_lock(lock_name)
+        .status(TaskStatus::Running)
+        .claimed_at(Utc::now())
+        .build()
+        .expect("task should be valid");
+    let task_id = task.id;
+    conn.put_task(task).unwrap();
+    conn.get_task(task_id).unwrap().unwrap()
+}
+
+fn is_poisoned(conn: &mut PgConnection, lock_name: &str) -> bool {
+    conn.get_poisoned_locks()
+        .unwrap()
+        .iter()
+        .any(|lock| lock.name == lock_name)
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn requested_cancellation_poisons_locks_when_enabled() {
+    common::test_transaction(|conn| {
+        let lock_name = format!("{}/router", Uuid::new_v4());
+        let mut task = running_task(conn, &lock_name);
+        conn.request_task_cancellation(task.id, &Actor::System)
+            .unwrap();
+
+        // the killed process exited with an error, but the task was cancelled
+        task.finish(TaskResult::Error);
+        assert_eq!(conn.finish_task(&task, true, &Actor::System).unwrap(), 1);
+
+        assert_eq!(
+            conn.get_task(task.id).unwrap().unwrap().status,
+            TaskStatus::Finished(TaskResult::Cancel)
+        );
+        assert!(is_poisoned(conn, &lock_name));
+    });
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn reported_cancellation_respects_lock_poisoning() {
+    common::test_transaction(|conn| {
+        for poison_locks_on_cancel in [false, true] {
+            let lock_name = format!("{}/router", Uuid::new_v4());
+            let mut task = running_task(conn, &lock_name);
+
+            task.finish(TaskResult::Cancel);
+            assert_eq!(
+                conn.finish_task(&task, poison_locks_on_cancel, &Actor::System)
+                    .unwrap(),
+                1
+            );
+
+            assert_eq!(
+                conn.get_task(task.id).unwrap().unwrap().status,
+                TaskStatus::Finished(TaskResult::Cancel)
+            );
+            assert_eq!(is_poisoned(conn, &lock_name), poison_locks_on_cancel);
+
+            let history = conn.get_task_events(task.id).unwrap();
+            assert_eq!(
+                history.last().and_then(|event| event.reason.clone()),
+                None,
+                "nobody requested the cancellation"
+            );
+        }
+    });
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn rejected_task_keeps_the_comment_without_poisoning() {
+    common::test_transaction(|conn| {
+        let lock_name = format!("{}/router", Uuid::new_v4());
+        let task = Task::builder()
+            .display_name("Risky deployment")
+            .write_lock(&lock_name)
+            .status(TaskStatus::NeedsUserValidation)
+            .build()
+            .expect("task should be valid");
+        let dependent = Task::builder()
+            .display_name("Follow-up deployment")
+            .status(TaskStatus::NeedsUserValidation)
+            .depends_on(task.id)
+            .build()
+            .expect("task should be valid");
+        let (task_id, dependent_id) = (task.id, dependent.id);
+        conn.put_task(task).unwrap();
+        conn.put_task(dependent).unwrap();
+
+        let rejection = TaskRejection {
+            comment: "not during the change freeze".to_string(),
+            rejected_at: Utc::now(),
+            rejected_by: None,
+        };
+        assert_eq!(
+            conn.reject_task(task_id, &rejection, &Actor::System)
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            conn.reject_task(task_id, &rejection, &Actor::System)
+                .unwrap(),
+            0,
+            "only tasks awaiting confirmation can be rejected"
+        );
+
+        let rejected = conn.get_task(task_id).unwrap().unwrap();
+        assert_eq!(rejected.status, TaskStatus::Finished(TaskResult::Cancel));
+        assert_eq!(
+            rejected.rejection.map(|rejection| rejection.comment),
+            Some(rejection.comment)
+        );
+        assert!(
+            !conn
+                .get_poisoned_locks()
+                .unwrap()
+                .iter()
+                .any(|lock| lock.name == lock_name)
+        );
+
+        assert_eq!(
+            conn.get_task(dependent_id).unwrap().unwrap().status,
+            TaskStatus::Finished(TaskResult::DependencyFailed)
+        );
+    });
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn cancellation_fails_transitive_dependents() {
+    common::test_transaction(|conn| {
+        let build = Task::builder()
+            .display_name("Build image")
+            .status(TaskStatus::NeedsUserValidation)
+            .build()
+            .expect("task should be valid");
+        let deploy = Task::builder()
+            .display_name("Deploy image")
+            .status(TaskStatus::NeedsUserValidation)
+            .depends_on(build.id)
+            .build()
+            .expect("task should be valid");
+        let verify = Task::builder()
+            .display_name("Verify deployment")
+            .status(TaskStatus::NeedsUserValidation)
+            .depends_on(deploy.id)
+            .build()
+            .expect("task should be valid");
+        let (build_id, deploy_id, verify_id) = (build.id, deploy.id, verify.id);
+        for task in [build, deploy, verify] {
+            conn.put_task(task).unwrap();
+        }
+
+        assert_eq!(
+            conn.cancel_task(build_id, false, &Actor::System).unwrap(),
+            1
+        );
+
+        for (task_id, dependency_id) in [(deploy_id, build_id), (verify_id, deploy_id)] {
+            assert_eq!(
+                conn.get_task(task_id).unwrap().unwrap().status,
+                TaskStatus::Finished(TaskResult::DependencyFailed)
+            );
+            let history = conn.get_task_events(task_id).unwrap();
+            assert_eq!(
+                history.last().and_then(|event| event.reason.clone()),
+                Some(format!("dependency {dependency_id} failed"))
+            );
+        }
+    });
+}
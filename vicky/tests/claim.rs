@@ -0,0 +1,138 @@
+mod common;
+
+use std::collections::HashSet;
+use std::thread;
+use uuid::Uuid;
+use vickylib::database::entities::Task;
+use vickylib::database::entities::task::db_impl::TaskDatabase;
+use vickylib::database::entities::task::{TaskResult, TaskStatus};
+use vickylib::database::entities::task_event::Actor;
+use vickylib::vicky::scheduler::{Scheduler, SchedulerConfig};
+
+const FAIRIES: usize = 8;
+
+/// The fairies need their own connections, so this test commits its tasks and cancels them in
+/// the end. Tasks of other tests are invisible to it, they are rolled back.
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn concurrent_claims_never_double_own_locks() {
+    let mut conn = common::connect();
+
+    let prefix = Uuid::new_v4();
+    let shared_lock = format!("{prefix}/shared");
+    let mut task_ids = Vec::new();
+
+    for i in 0..20 {
+        let writer = Task::builder()
+            .display_name(format!("Shared writer {i}"))
+            .write_lock(&shared_lock)
+            .build()
+            .expect("task should be valid");
+        let router = Task::builder()
+            .display_name(format!("Router {i}"))
+            .write_lock(format!("{prefix}/router{i}"))
+            .build()
+            .expect("task should be valid");
+
+        task_ids.extend([writer.id, router.id]);
+        conn.put_task(writer).unwrap();
+        conn.put_task(router).unwrap();
+    }
+
+    let claimed: Vec<Task> = thread::scope(|scope| {
+        let fairies: Vec<_> = (0..FAIRIES)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut conn = common::connect();
+                    // nothing finishes, so once nothing can be claimed, nothing ever will
+                    std::iter::from_fn(|| {
+                        conn.claim_next_task(&[], &SchedulerConfig::default(), &Actor::System)
+                            .unwrap()
+                    })
+                    .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        fairies
+            .into_iter()
+            .flat_map(|fairy| fairy.join().unwrap())
+            .collect()
+    });
+
+    let claimed: Vec<Task> = claimed
+        .into_iter()
+        .filter(|task| task_ids.contains(&task.id))
+        .collect();
+    let unique_ids: HashSet<Uuid> = claimed.iter().map(|task| task.id).collect();
+    assert_eq!(unique_ids.len(), claimed.len(), "a task was claimed twice");
+
+    let shared_writers = claimed
+        .iter()
+        .filter(|task| task.locks.iter().any(|lock| lock.name == shared_lock))
+        .count();
+    assert_eq!(
+        shared_writers, 1,
+        "the shared write lock was handed out twice"
+    );
+
+    let running: Vec<Task> = conn
+        .get_all_tasks_filtered(&[TaskStatus::Running], None)
+        .unwrap()
+        .into_iter()
+        .filter(|task| {
+            task.locks
+                .iter()
+                .any(|lock| lock.name.starts_with(&prefix.to_string()))
+        })
+        .collect();
+    assert_eq!(
+        running.len(),
+        21,
+        "all routers and one shared writer should run"
+    );
+    assert!(
+        Scheduler::new(&running, &[], &[]).is_ok(),
+        "running tasks hold conflicting locks"
+    );
+
+    for task_id in task_ids {
+        conn.cancel_task(task_id, false, &Actor::System).unwrap();
+    }
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn pending_tasks_include_finished_dependencies_only() {
+    common::test_transaction(|conn| {
+        let finished = |name: &str| {
+            Task::builder()
+                .display_name(name)
+                .status(TaskStatus::Finished(TaskResult::Success))
+                .build()
+                .expect("task should be valid")
+        };
+        let unrelated = finished("Unrelated history");
+        let dependency = finished("Finished dependency");
+        let pending = Task::builder()
+            .display_name("Pending")
+            .depends_on(dependency.id)
+            .build()
+            .expect("task should be valid");
+
+        let ids = [unrelated.id, dependency.id, pending.id];
+        conn.put_task(unrelated).unwrap();
+        conn.put_task(dependency).unwrap();
+        conn.put_task(pending).unwrap();
+
+        let loaded: HashSet<Uuid> = conn
+            .get_pending_tasks()
+            .unwrap()
+            .into_iter()
+            .map(|task| task.id)
+            .filter(|id| ids.contains(id))
+            .collect();
+
+        assert_eq!(loaded, HashSet::from([ids[1], ids[2]]));
+    });
+}
@@ -0,0 +1,32 @@
+//! The database tests need a disposable postgres database, passed via `VICKY_TEST_DATABASE_URL`.
+//! They are ignored by default, run them with `cargo test -- --ignored`.
+
+use diesel::{Connection, PgConnection};
+use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
+use std::convert::Infallible;
+use std::sync::Once;
+
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+
+static MIGRATED: Once = Once::new();
+
+/// Connects to the test database, which is migrated by the first connection of a test binary.
+pub fn connect() -> PgConnection {
+    let url = std::env::var("VICKY_TEST_DATABASE_URL")
+        .expect("VICKY_TEST_DATABASE_URL should point to a disposable database");
+    let mut conn = PgConnection::establish(&url).expect("test database should be reachable");
+    MIGRATED.call_once(|| {
+        conn.run_pending_migrations(MIGRATIONS)
+            .expect("migrations should apply");
+    });
+    conn
+}
+
+/// Runs a test in a transaction that is rolled back afterwards, so tests don't see each other's
+/// tasks. Tests that need several connections can't use it and have to clean up themselves.
+pub fn test_transaction(test: impl FnOnce(&mut PgConnection)) {
+    connect().test_transaction(|conn| {
+        test(conn);
+        Ok::<_, Infallible>(())
+    })
+}
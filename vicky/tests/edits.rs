@@ -0,0 +1,59 @@
+mod common;
+
+use vickylib::database::entities::task::TaskStatus;
+use vickylib::database::entities::task::db_impl::TaskDatabase;
+use vickylib::database::entities::task_edit::TaskEdit;
+use vickylib::database::entities::task_edit::db_impl::TaskEditDatabase;
+use vickylib::database::entities::task_event::Actor;
+use vickylib::database::entities::{Lock, Task};
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn task_awaiting_confirmation_is_edited_with_history() {
+    common::test_transaction(|conn| {
+        let task = Task::builder()
+            .display_name("Deploy routr")
+            .status(TaskStatus::NeedsUserValidation)
+            .flake_args(vec!["--target".to_string(), "routr".to_string()])
+            .read_lock("config")
+            .build()
+            .expect("task should be valid");
+        let task_id = task.id;
+        conn.put_task(task).unwrap();
+
+        let mut task = conn.get_task(task_id).unwrap().unwrap();
+        let mut edit = TaskEdit::new(task_id, None);
+        edit.record(
+            "display_name",
+            &mut task.display_name,
+            Some("Deploy router".to_string()),
+        );
+        edit.record(
+            "flake_args",
+            &mut task.flake_ref.args,
+            Some(vec!["--target".to_string(), "router".to_string()]),
+        );
+        edit.record("locks", &mut task.locks, Some(vec![Lock::write("config")]));
+        edit.record("priority", &mut task.priority, Some(0));
+        assert_eq!(edit.changes.len(), 3, "unchanged fields aren't recorded");
+
+        assert_eq!(conn.edit_task(&task, &edit).unwrap(), 1);
+
+        let edited = conn.get_task(task_id).unwrap().unwrap();
+        assert_eq!(edited.display_name, "Deploy router");
+        assert_eq!(edited.flake_ref.args, vec!["--target", "router"]);
+        assert_eq!(edited.locks, vec![Lock::write("config")]);
+
+        let history = conn.get_task_edits(task_id).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].changes, edit.changes);
+
+        conn.confirm_task(task_id, &Actor::System).unwrap();
+        assert_eq!(
+            conn.edit_task(&task, &TaskEdit::new(task_id, None))
+                .unwrap(),
+            0,
+            "confirmed tasks can't be edited"
+        );
+    });
+}
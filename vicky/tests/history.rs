@@ -0,0 +1,118 @@
+mod common;
+
+use chrono::{TimeDelta, Utc};
+use vickylib::database::entities::Task;
+use vickylib::database::entities::task::db_impl::TaskDatabase;
+use vickylib::database::entities::task::{TaskResult, TaskStatus};
+use vickylib::database::entities::task_event::db_impl::TaskEventDatabase;
+use vickylib::database::entities::task_event::{Actor, TaskEvent};
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn timeout_sweep_records_the_transition() {
+    common::test_transaction(|conn| {
+        let claimed_at = Utc::now() - TimeDelta::hours(2);
+        let task = Task::builder()
+            .display_name("Stuck deployment")
+            .status(TaskStatus::Running)
+            .claimed_at(claimed_at)
+            .last_heartbeat(Utc::now())
+            .max_runtime(3600)
+            .build()
+            .expect("task should be valid");
+        let task_id = task.id;
+        conn.put_task(task).unwrap();
+
+        let claimed = TaskEvent {
+            created_at: claimed_at,
+            ..TaskEvent::new(
+                task_id,
+                TaskStatus::New,
+                TaskStatus::Running,
+                Actor::Machine {
+                    name: "fairy-1".to_string(),
+                },
+            )
+        };
+        conn.put_task_event(&claimed).unwrap();
+
+        conn.perform_timeout_sweep(TimeDelta::hours(6)).unwrap();
+
+        let history = conn.get_task_events(task_id).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].actor, claimed.actor);
+        assert_eq!(history[0].to_status, TaskStatus::Running);
+
+        let timed_out = &history[1];
+        assert_eq!(timed_out.from_status, TaskStatus::Running);
+        assert_eq!(
+            timed_out.to_status,
+            TaskStatus::Finished(TaskResult::Timeout)
+        );
+        assert_eq!(timed_out.actor, Actor::System);
+        assert_eq!(
+            timed_out.reason.as_deref(),
+            Some("the maximum runtime was exceeded")
+        );
+    });
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn failed_dependency_records_the_cascade() {
+    common::test_transaction(|conn| {
+        let mut build = Task::builder()
+            .display_name("Build image")
+            .status(TaskStatus::Running)
+            .build()
+            .expect("task should be valid");
+        let deploy = Task::builder()
+            .display_name("Deploy image")
+            .status(TaskStatus::NeedsUserValidation)
+            .needs_confirmation(true)
+            .depends_on(build.id)
+            .build()
+            .expect("task should be valid");
+        let verify = Task::builder()
+            .display_name("Verify deployment")
+            .depends_on(deploy.id)
+            .build()
+            .expect("task should be valid");
+        let (build_id, deploy_id, verify_id) = (build.id, deploy.id, verify.id);
+        conn.put_task(build.clone()).unwrap();
+        conn.put_task(deploy).unwrap();
+        conn.put_task(verify).unwrap();
+
+        let fairy = Actor::Machine {
+            name: "fairy-1".to_string(),
+        };
+        build.finish(TaskResult::Error);
+        assert_eq!(conn.finish_task(&build, false, &fairy).unwrap(), 1);
+
+        let finished = conn.get_task_events(build_id).unwrap();
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].actor, fairy);
+        assert_eq!(
+            finished[0].to_status,
+            TaskStatus::Finished(TaskResult::Error)
+        );
+
+        for (task_id, from_status, failed_dependency) in [
+            (deploy_id, TaskStatus::NeedsUserValidation, build_id),
+            (verify_id, TaskStatus::New, deploy_id),
+        ] {
+            let history = conn.get_task_events(task_id).unwrap();
+            assert_eq!(history.len(), 1);
+            assert_eq!(history[0].from_status, from_status);
+            assert_eq!(
+                history[0].to_status,
+                TaskStatus::Finished(TaskResult::DependencyFailed)
+            );
+            assert_eq!(history[0].actor, Actor::System);
+            assert_eq!(
+                history[0].reason,
+                Some(format!("dependency {failed_dependency} failed"))
+            );
+        }
+    });
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use uuid::Uuid;
+use vickylib::database::entities::Task;
+use vickylib::database::entities::task::TaskStatus;
+use vickylib::database::entities::task::db_impl::TaskDatabase;
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn repeated_submission_keeps_the_original_task() {
+    common::test_transaction(|conn| {
+        let key = format!("pipeline-{}", Uuid::new_v4());
+        let submission = || {
+            Task::builder()
+                .display_name("Deploy core router")
+                .write_lock(format!("{}/router", Uuid::new_v4()))
+                .status(TaskStatus::NeedsUserValidation)
+                .idempotency_key(key.clone())
+                .build()
+                .expect("task should be valid")
+        };
+        let (original, repeated) = (submission(), submission());
+        let (original_id, repeated_id) = (original.id, repeated.id);
+
+        assert_eq!(conn.put_task(original).unwrap(), 1);
+        assert_eq!(
+            conn.put_task(repeated).unwrap(),
+            0,
+            "the key was used already"
+        );
+        assert!(conn.get_task(repeated_id).unwrap().is_none());
+
+        let found = conn.get_task_by_idempotency_key(&key).unwrap().unwrap();
+        assert_eq!(found.id, original_id);
+        assert_eq!(found.idempotency_key.as_deref(), Some(key.as_str()));
+        assert!(
+            conn.get_task_by_idempotency_key("unused")
+                .unwrap()
+                .is_none()
+        );
+    });
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use uuid::Uuid;
+use vickylib::database::entities::Task;
+use vickylib::database::entities::task::db_impl::TaskDatabase;
+use vickylib::query::FilterParams;
+
+fn labelled(selectors: &[&str]) -> FilterParams {
+    FilterParams {
+        label: selectors.iter().map(|s| s.to_string()).collect(),
+        ..FilterParams::default()
+    }
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn tasks_are_filtered_by_all_label_selectors() {
+    common::test_transaction(|conn| {
+        // a unique team keeps tasks of other tests out of the results
+        let team = format!("team={}", Uuid::new_v4());
+        let (team_key, team_value) = team.split_once('=').unwrap();
+
+        let prod = Task::builder()
+            .display_name("Prod deployment")
+            .label(team_key, team_value)
+            .label("env", "prod")
+            .build()
+            .expect("task should be valid");
+        let staging = Task::builder()
+            .display_name("Staging deployment")
+            .label(team_key, team_value)
+            .label("env", "staging")
+            .build()
+            .expect("task should be valid");
+        let prod_id = prod.id;
+        conn.put_task(prod).unwrap();
+        conn.put_task(staging).unwrap();
+
+        let loaded = conn.get_task(prod_id).unwrap().unwrap();
+        assert_eq!(loaded.labels.get("env").map(String::as_str), Some("prod"));
+
+        assert_eq!(conn.count_all_tasks(&[], labelled(&[&team])).unwrap(), 2);
+
+        let prod_only = conn
+            .get_all_tasks_filtered(&[], labelled(&[&team, "env=prod"]))
+            .unwrap();
+        assert_eq!(prod_only.len(), 1);
+        assert_eq!(prod_only[0].id, prod_id);
+
+        assert_eq!(
+            conn.count_all_tasks(&[], labelled(&[&team, "env"]))
+                .unwrap(),
+            2,
+            "a bare key matches any value"
+        );
+        assert_eq!(
+            conn.count_all_tasks(&[], labelled(&[&team, "env=dev"]))
+                .unwrap(),
+            0
+        );
+    });
+}
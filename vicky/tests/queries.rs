@@ -0,0 +1,219 @@
+mod common;
+
+use chrono::{DateTime, TimeDelta};
+use uuid::Uuid;
+use vickylib::database::entities::Task;
+use vickylib::database::entities::task::db_impl::TaskDatabase;
+use vickylib::database::entities::task::{TaskResult, TaskStatus};
+use vickylib::query::{FilterParams, SortOrder, TaskCursor, TaskSort};
+
+fn display_names(tasks: &[Task]) -> Vec<&str> {
+    tasks.iter().map(|t| t.display_name.as_str()).collect()
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn tasks_are_filtered_and_sorted() {
+    common::test_transaction(|conn| {
+        // a unique lock keeps tasks of other tests out of the results
+        let lock_name = format!("{}/router", Uuid::new_v4());
+        let base = DateTime::from_timestamp(1_775_000_000, 0).unwrap();
+        let finished = |status| TaskStatus::Finished(status);
+
+        let tasks = [
+            Task::builder()
+                .display_name("Deploy Router 1")
+                .read_lock(&lock_name)
+                .flake("gitlab:wobcom/routers")
+                .status(finished(TaskResult::Success))
+                .created_at(base)
+                .finished_at(base + TimeDelta::hours(3))
+                .priority(5)
+                .build()
+                .expect("task should be valid"),
+            Task::builder()
+                .display_name("deploy router 2")
+                .read_lock(&lock_name)
+                .flake("gitlab:wobcom/routers_100%")
+                .status(finished(TaskResult::Error))
+                .created_at(base + TimeDelta::hours(1))
+                .finished_at(base + TimeDelta::hours(2))
+                .build()
+                .expect("task should be valid"),
+            Task::builder()
+                .display_name("Backup Switch 1")
+                .read_lock(&lock_name)
+                .flake("gitlab:wobcom/switches")
+                .status(TaskStatus::NeedsUserValidation)
+                .created_at(base + TimeDelta::hours(2))
+                .priority(-1)
+                .build()
+                .expect("task should be valid"),
+        ];
+        for task in tasks {
+            conn.put_task(task).unwrap();
+        }
+
+        let query = |filters: FilterParams| FilterParams {
+            lock: Some(lock_name.clone()),
+            ..filters
+        };
+
+        let newest_first = conn
+            .get_all_tasks_filtered(&[], query(FilterParams::default()))
+            .unwrap();
+        assert_eq!(
+            display_names(&newest_first),
+            ["Backup Switch 1", "deploy router 2", "Deploy Router 1"]
+        );
+
+        let finished_tasks = [finished(TaskResult::Success), finished(TaskResult::Error)];
+        assert_eq!(
+            conn.count_all_tasks(&finished_tasks, query(FilterParams::default()))
+                .unwrap(),
+            2
+        );
+
+        let searched = conn
+            .get_all_tasks_filtered(
+                &[],
+                query(FilterParams {
+                    search: Some("ROUTER".to_string()),
+                    sort: Some(TaskSort::DisplayName),
+                    order: Some(SortOrder::Asc),
+                    ..FilterParams::default()
+                }),
+            )
+            .unwrap();
+        assert_eq!(
+            display_names(&searched),
+            ["Deploy Router 1", "deploy router 2"],
+            "the search ignores the case"
+        );
+
+        assert_eq!(
+            conn.count_all_tasks(
+                &[],
+                query(FilterParams {
+                    flake: Some("100%".to_string()),
+                    ..FilterParams::default()
+                })
+            )
+            .unwrap(),
+            1,
+            "wildcards in the flake are matched literally"
+        );
+
+        assert_eq!(
+            conn.count_all_tasks(
+                &[],
+                query(FilterParams {
+                    created_after: Some((base + TimeDelta::hours(1)).timestamp()),
+                    created_before: Some((base + TimeDelta::hours(2)).timestamp()),
+                    ..FilterParams::default()
+                })
+            )
+            .unwrap(),
+            1
+        );
+
+        let by_finish = conn
+            .get_all_tasks_filtered(
+                &[],
+                query(FilterParams {
+                    sort: Some(TaskSort::FinishedAt),
+                    order: Some(SortOrder::Asc),
+                    ..FilterParams::default()
+                }),
+            )
+            .unwrap();
+        assert_eq!(
+            display_names(&by_finish),
+            ["deploy router 2", "Deploy Router 1", "Backup Switch 1"],
+            "unfinished tasks come last"
+        );
+
+        let by_priority = conn
+            .get_all_tasks_filtered(
+                &[],
+                query(FilterParams {
+                    finished_after: Some(base.timestamp()),
+                    sort: Some(TaskSort::Priority),
+                    ..FilterParams::default()
+                }),
+            )
+            .unwrap();
+        assert_eq!(
+            display_names(&by_priority),
+            ["Deploy Router 1", "deploy router 2"]
+        );
+
+        assert_eq!(
+            conn.count_all_tasks(
+                &[],
+                FilterParams {
+                    lock: Some(format!("{lock_name}/other")),
+                    ..FilterParams::default()
+                }
+            )
+            .unwrap(),
+            0
+        );
+    });
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn cursor_pages_are_stable_while_tasks_are_added() {
+    common::test_transaction(|conn| {
+        let lock_name = format!("{}/router", Uuid::new_v4());
+        let base = DateTime::from_timestamp(1_775_000_000, 0).unwrap();
+
+        // two tasks share their creation time, so the id has to break the tie
+        let created = [0, 1, 1, 2, 3];
+        let mut ids = vec![];
+        for hours in created {
+            let task = Task::builder()
+                .read_lock(&lock_name)
+                .status(TaskStatus::Finished(TaskResult::Success))
+                .created_at(base + TimeDelta::hours(hours))
+                .build()
+                .expect("task should be valid");
+            ids.push(task.id);
+            conn.put_task(task).unwrap();
+        }
+
+        let mut seen = vec![];
+        let mut filters = FilterParams {
+            lock: Some(lock_name.clone()),
+            limit: Some(2),
+            ..FilterParams::default()
+        };
+        loop {
+            let page = conn.get_all_tasks_filtered(&[], filters.clone()).unwrap();
+            seen.extend(page.iter().map(|t| t.id));
+
+            if seen.len() == 2 {
+                let newer = Task::builder()
+                    .read_lock(&lock_name)
+                    .status(TaskStatus::Finished(TaskResult::Success))
+                    .build()
+                    .expect("task should be valid");
+                conn.put_task(newer).unwrap();
+            }
+
+            let Some(cursor) = filters.next_cursor(&page) else {
+                break;
+            };
+            assert_eq!(TaskCursor::decode(&cursor.encode()), Some(cursor));
+            filters.cursor = Some(cursor.encode());
+        }
+
+        ids.sort();
+        seen.sort();
+        assert_eq!(
+            seen, ids,
+            "every task is listed exactly once, the one added later isn't"
+        );
+    });
+}
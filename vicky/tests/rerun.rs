@@ -0,0 +1,106 @@
+mod common;
+
+use diesel::PgConnection;
+use uuid::Uuid;
+use vickylib::database::entities::Task;
+use vickylib::database::entities::lock::db_impl::LockDatabase;
+use vickylib::database::entities::task::db_impl::TaskDatabase;
+use vickylib::database::entities::task::{RetryPolicy, TaskResult, TaskStatus};
+use vickylib::database::entities::user::db_impl::UserDatabase;
+use vickylib::database::entities::user::{Role, User};
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn rerun_clears_poison_of_original_task() {
+    common::test_transaction(|conn| {
+        let lock_name = format!("{}/router", Uuid::new_v4());
+        let failed = Task::builder()
+            .display_name("Deploy router")
+            .write_lock(&lock_name)
+            .needs_confirmation(true)
+            .status(TaskStatus::Running)
+            .build()
+            .expect("task should be valid");
+        let failed_id = failed.id;
+        conn.put_task(failed).unwrap();
+
+        let mut failed = conn.get_task(failed_id).unwrap().unwrap();
+        failed.finish(TaskResult::Error);
+        conn.update_task(&failed).unwrap();
+
+        let is_poisoned = |conn: &mut PgConnection| {
+            conn.get_poisoned_locks()
+                .unwrap()
+                .iter()
+                .any(|lock| lock.name == lock_name)
+        };
+        assert!(is_poisoned(conn));
+
+        let rerun = conn
+            .rerun_task(&failed, true, None)
+            .unwrap()
+            .expect("the rerun should be valid");
+        assert!(!is_poisoned(conn));
+
+        let rerun = conn.get_task(rerun.id).unwrap().unwrap();
+        assert_eq!(rerun.rerun_of, Some(failed_id));
+        assert_eq!(rerun.status, TaskStatus::NeedsUserValidation);
+        assert!(rerun.needs_confirmation);
+        assert_eq!(rerun.locks.len(), 1);
+        assert!(!rerun.locks[0].is_poisoned());
+    });
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn rerun_of_retried_task_needs_confirmation_again() {
+    common::test_transaction(|conn| {
+        let creator = User {
+            id: Uuid::new_v4(),
+            name: "alice".to_string(),
+            role: Role::Admin,
+        };
+        let creator_id = creator.id;
+        conn.upsert_user(creator).unwrap();
+        let mut first = Task::builder()
+            .display_name("Deploy router")
+            .status(TaskStatus::Running)
+            .needs_confirmation(true)
+            .required_approvals(2)
+            .created_by(creator_id)
+            .retry_policy(RetryPolicy {
+                max_attempts: 2,
+                backoff_sec: 0,
+                retry_on: vec![TaskResult::Error],
+            })
+            .build()
+            .expect("task should be valid");
+        let first_id = first.id;
+        conn.put_task(first.clone()).unwrap();
+
+        first.finish(TaskResult::Error);
+        conn.update_task(&first).unwrap();
+
+        let retry = conn
+            .get_latest_attempt(first_id)
+            .unwrap()
+            .expect("the task should be retried");
+        assert_eq!(retry.retry_of, Some(first_id));
+        assert_eq!(
+            retry.status,
+            TaskStatus::New,
+            "the retry was confirmed already"
+        );
+        assert!(retry.needs_confirmation);
+        assert_eq!(retry.required_approvals, 2);
+        assert_eq!(retry.created_by, Some(creator_id));
+
+        let rerun = conn
+            .rerun_task(&retry, false, None)
+            .unwrap()
+            .expect("the rerun should be valid");
+        let rerun = conn.get_task(rerun.id).unwrap().unwrap();
+        assert_eq!(rerun.status, TaskStatus::NeedsUserValidation);
+        assert_eq!(rerun.required_approvals, 2);
+    });
+}
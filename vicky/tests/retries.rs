@@ -0,0 +1,102 @@
+mod common;
+
+use diesel::PgConnection;
+use uuid::Uuid;
+use vickylib::database::entities::Task;
+use vickylib::database::entities::lock::db_impl::LockDatabase;
+use vickylib::database::entities::task::db_impl::TaskDatabase;
+use vickylib::database::entities::task::{RetryPolicy, TaskResult, TaskStatus};
+
+fn find_retry(conn: &mut PgConnection, task_id: Uuid) -> Option<Task> {
+    conn.get_pending_tasks()
+        .unwrap()
+        .into_iter()
+        .find(|task| task.retry_of == Some(task_id))
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn failed_task_is_retried_until_attempts_run_out() {
+    common::test_transaction(|conn| {
+        let lock_name = format!("{}/router", Uuid::new_v4());
+        let flaky = Task::builder()
+            .display_name("Flaky fetch")
+            .write_lock(&lock_name)
+            .retry_policy(RetryPolicy {
+                max_attempts: 2,
+                backoff_sec: 30,
+                retry_on: vec![TaskResult::Error],
+            })
+            .build()
+            .expect("task should be valid");
+        let dependent = Task::builder()
+            .display_name("Deploy")
+            .depends_on(flaky.id)
+            .build()
+            .expect("task should be valid");
+        let (flaky_id, dependent_id) = (flaky.id, dependent.id);
+        conn.put_task(flaky).unwrap();
+        conn.put_task(dependent).unwrap();
+
+        let mut first = conn.get_task(flaky_id).unwrap().unwrap();
+        first.finish(TaskResult::Error);
+        conn.update_task(&first).unwrap();
+
+        let second = find_retry(conn, flaky_id).expect("the task should be retried");
+        assert_eq!(second.attempt, 2);
+        assert_eq!(second.status, TaskStatus::New);
+        assert!(second.not_before.is_some());
+        assert_eq!(
+            conn.get_task(dependent_id).unwrap().unwrap().depends_on,
+            vec![second.id]
+        );
+        assert_eq!(
+            conn.get_latest_attempt(flaky_id)
+                .unwrap()
+                .map(|task| task.id),
+            Some(second.id)
+        );
+        let late_dependent = Task::builder()
+            .display_name("Late deploy")
+            .depends_on(flaky_id)
+            .build()
+            .expect("task should be valid");
+        let late_dependent_id = late_dependent.id;
+        conn.put_task(late_dependent).unwrap();
+        assert_eq!(
+            conn.get_task(late_dependent_id)
+                .unwrap()
+                .unwrap()
+                .depends_on,
+            vec![second.id],
+            "a new dependent waits for the latest attempt, not the failed one"
+        );
+        assert!(
+            !conn
+                .get_poisoned_locks()
+                .unwrap()
+                .iter()
+                .any(|lock| lock.name == lock_name),
+            "a retried task must not poison its locks"
+        );
+
+        let mut second = second;
+        second.finish(TaskResult::Error);
+        conn.update_task(&second).unwrap();
+
+        assert!(find_retry(conn, second.id).is_none());
+        for task_id in [dependent_id, late_dependent_id] {
+            assert_eq!(
+                conn.get_task(task_id).unwrap().unwrap().status,
+                TaskStatus::Finished(TaskResult::DependencyFailed)
+            );
+        }
+        assert!(
+            conn.get_poisoned_locks()
+                .unwrap()
+                .iter()
+                .any(|lock| lock.name == lock_name),
+            "the last attempt poisons its locks"
+        );
+    });
+}
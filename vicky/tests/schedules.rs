@@ -0,0 +1,59 @@
+mod common;
+
+use chrono::{DateTime, TimeDelta};
+use uuid::Uuid;
+use vickylib::database::entities::schedule::db_impl::ScheduleDatabase;
+use vickylib::database::entities::task::db_impl::TaskDatabase;
+use vickylib::database::entities::task::{FlakeRef, TaskResult, TaskStatus};
+use vickylib::database::entities::{Lock, Schedule};
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn schedule_skips_run_while_previous_task_is_pending() {
+    common::test_transaction(|conn| {
+        // runs are driven by the passed time, so a schedule far in the past doesn't interfere with others
+        let start = DateTime::from_timestamp(946_684_800, 0).unwrap();
+        let minute = TimeDelta::minutes(1);
+
+        let schedule = Schedule {
+            id: Uuid::new_v4(),
+            display_name: "Config drift check".to_string(),
+            cron: "* * * * *".to_string(),
+            flake_ref: FlakeRef {
+                flake: "github:wobcom/vicky".to_string(),
+                args: vec![],
+            },
+            locks: vec![Lock::read("config")],
+            features: vec![],
+            group: Some("drift".to_string()),
+            created_at: start,
+            next_run_at: Some(start),
+            last_task_id: None,
+        };
+        conn.put_schedule(&schedule).unwrap();
+
+        assert_eq!(conn.run_due_schedules(start).unwrap(), (1, 0));
+        let first_run = conn.get_schedule(schedule.id).unwrap().unwrap();
+        assert_eq!(first_run.next_run_at, Some(start + minute));
+        let first_task_id = first_run.last_task_id.expect("a task should be created");
+
+        let mut first_task = conn.get_task(first_task_id).unwrap().unwrap();
+        assert_eq!(first_task.status, TaskStatus::New);
+        assert_eq!(first_task.locks, schedule.locks);
+        assert_eq!(first_task.group, schedule.group);
+
+        assert_eq!(conn.run_due_schedules(start + minute).unwrap(), (0, 1));
+        let skipped_run = conn.get_schedule(schedule.id).unwrap().unwrap();
+        assert_eq!(skipped_run.next_run_at, Some(start + minute * 2));
+        assert_eq!(skipped_run.last_task_id, Some(first_task_id));
+
+        first_task.finish(TaskResult::Success);
+        conn.update_task(&first_task).unwrap();
+
+        assert_eq!(conn.run_due_schedules(start + minute * 2).unwrap(), (1, 0));
+        let second_run = conn.get_schedule(schedule.id).unwrap().unwrap();
+        assert_ne!(second_run.last_task_id, Some(first_task_id));
+
+        conn.delete_schedule(schedule.id).unwrap();
+    });
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use chrono::{TimeDelta, Utc};
+use diesel::PgConnection;
+use uuid::Uuid;
+use vickylib::database::entities::Task;
+use vickylib::database::entities::lock::db_impl::LockDatabase;
+use vickylib::database::entities::task::db_impl::TaskDatabase;
+use vickylib::database::entities::task::{TaskResult, TaskStatus};
+use vickylib::database::entities::task_event::Actor;
+use vickylib::database::entities::task_event::db_impl::TaskEventDatabase;
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn newer_task_supersedes_waiting_tasks_with_the_same_key() {
+    common::test_transaction(|conn| {
+        let key = format!("deploy-{}", Uuid::new_v4());
+        let lock_name = format!("{}/router", Uuid::new_v4());
+        let an_hour_ago = Utc::now() - TimeDelta::hours(1);
+        let deployment = |status: TaskStatus| {
+            Task::builder()
+                .display_name("Deploy core router")
+                .write_lock(&lock_name)
+                .status(status)
+                .created_at(an_hour_ago)
+                .supersede_key(key.clone())
+                // keeps waiting tasks from being claimed by other tests
+                .requires_features(vec![Uuid::new_v4().to_string()])
+        };
+
+        let waiting = deployment(TaskStatus::NeedsUserValidation)
+            .build()
+            .expect("task should be valid");
+        let queued = deployment(TaskStatus::New)
+            .build()
+            .expect("task should be valid");
+        let running = deployment(TaskStatus::Running)
+            .claimed_at(an_hour_ago)
+            .build()
+            .expect("task should be valid");
+        let dependent = Task::builder()
+            .display_name("Verify core router")
+            .status(TaskStatus::NeedsUserValidation)
+            .depends_on(queued.id)
+            .build()
+            .expect("task should be valid");
+        let newest = Task::builder()
+            .display_name("Deploy core router")
+            .status(TaskStatus::NeedsUserValidation)
+            .supersede_key(key.clone())
+            .build()
+            .expect("task should be valid");
+        let ids = [waiting.id, queued.id, running.id, dependent.id, newest.id];
+        for task in [waiting, queued, running, dependent, newest] {
+            conn.put_task(task).unwrap();
+        }
+        let [waiting_id, queued_id, running_id, dependent_id, newest_id] = ids;
+
+        let status_of = |conn: &mut PgConnection, id| conn.get_task(id).unwrap().unwrap().status;
+        for id in [waiting_id, queued_id] {
+            assert_eq!(
+                status_of(conn, id),
+                TaskStatus::Finished(TaskResult::Superseded)
+            );
+        }
+        assert_eq!(status_of(conn, running_id), TaskStatus::Running);
+        assert_eq!(status_of(conn, newest_id), TaskStatus::NeedsUserValidation);
+        assert_eq!(
+            status_of(conn, dependent_id),
+            TaskStatus::NeedsUserValidation
+        );
+        assert_eq!(
+            conn.get_task(dependent_id).unwrap().unwrap().depends_on,
+            vec![newest_id],
+            "dependents wait for the superseding task"
+        );
+        assert!(
+            !conn
+                .get_poisoned_locks()
+                .unwrap()
+                .iter()
+                .any(|lock| lock.name == lock_name)
+        );
+
+        let history = conn.get_task_events(queued_id).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].from_status, TaskStatus::New);
+        assert_eq!(history[0].actor, Actor::System);
+        assert_eq!(
+            history[0].reason,
+            Some(format!("superseded by task {newest_id}"))
+        );
+    });
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn finished_dependents_keep_their_dependencies() {
+    common::test_transaction(|conn| {
+        let key = format!("deploy-{}", Uuid::new_v4());
+        let queued = Task::builder()
+            .display_name("Deploy core router")
+            .created_at(Utc::now() - TimeDelta::hours(1))
+            .supersede_key(key.clone())
+            // keeps the task from being claimed by other tests
+            .requires_features(vec![Uuid::new_v4().to_string()])
+            .build()
+            .expect("task should be valid");
+        let cancelled = Task::builder()
+            .display_name("Verify core router")
+            .status(TaskStatus::Finished(TaskResult::Cancel))
+            .depends_on(queued.id)
+            .build()
+            .expect("task should be valid");
+        let newest = Task::builder()
+            .display_name("Deploy core router")
+            .status(TaskStatus::NeedsUserValidation)
+            .supersede_key(key)
+            .build()
+            .expect("task should be valid");
+        let (queued_id, cancelled_id) = (queued.id, cancelled.id);
+        for task in [queued, cancelled, newest] {
+            conn.put_task(task).unwrap();
+        }
+
+        assert_eq!(
+            conn.get_task(queued_id).unwrap().unwrap().status,
+            TaskStatus::Finished(TaskResult::Superseded)
+        );
+        assert_eq!(
+            conn.get_task(cancelled_id).unwrap().unwrap().depends_on,
+            vec![queued_id]
+        );
+    });
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn task_with_failed_dependency_supersedes_nothing() {
+    common::test_transaction(|conn| {
+        let key = format!("deploy-{}", Uuid::new_v4());
+        let queued = Task::builder()
+            .display_name("Deploy core router")
+            .created_at(Utc::now() - TimeDelta::hours(1))
+            .supersede_key(key.clone())
+            .build()
+            .expect("task should be valid");
+        let failed = Task::builder()
+            .display_name("Build image")
+            .status(TaskStatus::Finished(TaskResult::Error))
+            .build()
+            .expect("task should be valid");
+        let doomed = Task::builder()
+            .display_name("Deploy core router")
+            .depends_on(failed.id)
+            .supersede_key(key)
+            .build()
+            .expect("task should be valid");
+        let (queued_id, doomed_id) = (queued.id, doomed.id);
+        for task in [queued, failed, doomed] {
+            conn.put_task(task).unwrap();
+        }
+
+        assert_eq!(
+            conn.get_task(doomed_id).unwrap().unwrap().status,
+            TaskStatus::Finished(TaskResult::DependencyFailed)
+        );
+        assert_eq!(
+            conn.get_task(queued_id).unwrap().unwrap().status,
+            TaskStatus::New
+        );
+    });
+}
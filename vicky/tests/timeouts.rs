@@ -0,0 +1,91 @@
+mod common;
+
+use chrono::{TimeDelta, Utc};
+use uuid::Uuid;
+use vickylib::database::entities::Task;
+use vickylib::database::entities::lock::db_impl::LockDatabase;
+use vickylib::database::entities::task::db_impl::TaskDatabase;
+use vickylib::database::entities::task::{TaskResult, TaskStatus};
+use vickylib::database::entities::task_event::Actor;
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn task_exceeding_max_runtime_times_out() {
+    common::test_transaction(|conn| {
+        let lock_name = format!("{}/router", Uuid::new_v4());
+        let claimed_at = Utc::now() - TimeDelta::hours(2);
+
+        let limited = Task::builder()
+            .display_name("Stuck deployment")
+            .write_lock(&lock_name)
+            .status(TaskStatus::Running)
+            .claimed_at(claimed_at)
+            .max_runtime(3600)
+            .build()
+            .expect("task should be valid");
+        let unlimited = Task::builder()
+            .display_name("Long backup")
+            .status(TaskStatus::Running)
+            .claimed_at(claimed_at)
+            .build()
+            .expect("task should be valid");
+        let (limited_id, unlimited_id) = (limited.id, unlimited.id);
+        conn.put_task(limited).unwrap();
+        conn.put_task(unlimited).unwrap();
+
+        let sweep = conn.perform_timeout_sweep(TimeDelta::hours(6)).unwrap();
+        assert!(sweep.runtime_timeouts >= 1);
+
+        let mut limited = conn.get_task(limited_id).unwrap().unwrap();
+        assert_eq!(limited.status, TaskStatus::Finished(TaskResult::Timeout));
+        assert!(limited.finished_at.is_some());
+
+        // the fairy reports its result after the sweep, which must not overwrite the timeout
+        limited.finish(TaskResult::Success);
+        assert_eq!(
+            conn.finish_task(&limited, false, &Actor::System).unwrap(),
+            0
+        );
+        assert_eq!(
+            conn.get_task(limited_id).unwrap().unwrap().status,
+            TaskStatus::Finished(TaskResult::Timeout)
+        );
+        assert!(
+            conn.get_poisoned_locks()
+                .unwrap()
+                .iter()
+                .any(|lock| lock.name == lock_name),
+            "a timed out task poisons its locks"
+        );
+
+        assert_eq!(
+            conn.get_task(unlimited_id).unwrap().unwrap().status,
+            TaskStatus::Running,
+            "tasks without their own limit fall back to the default"
+        );
+    });
+}
+
+#[test]
+#[ignore = "needs VICKY_TEST_DATABASE_URL"]
+fn task_without_heartbeat_times_out_with_finish_time() {
+    common::test_transaction(|conn| {
+        let silent = Task::builder()
+            .display_name("Lost fairy")
+            .status(TaskStatus::Running)
+            .claimed_at(Utc::now() - TimeDelta::minutes(10))
+            .build()
+            .expect("task should be valid");
+        let silent_id = silent.id;
+        conn.put_task(silent).unwrap();
+        conn.register_task_heartbeat(silent_id, (Utc::now() - TimeDelta::minutes(10)).naive_utc())
+            .unwrap();
+
+        let sweep = conn.perform_timeout_sweep(TimeDelta::hours(6)).unwrap();
+        assert!(sweep.heartbeat_timeouts >= 1);
+
+        let silent = conn.get_task(silent_id).unwrap().unwrap();
+        assert_eq!(silent.status, TaskStatus::Finished(TaskResult::Timeout));
+        assert!(silent.finished_at.is_some());
+    });
+}
@@ -1,7 +1,9 @@
+use chrono::{DateTime, Utc};
 use clap::{Args, Parser, Subcommand};
 use uuid::Uuid;
 use vickylib::database::entities::LockKind;
 use vickylib::database::entities::task::TaskResult;
+use vickylib::query::{SortOrder, TaskSort};
 
 // TODO: Add abouts to arguments
 #[derive(Parser, Debug, Clone)]
@@ -24,6 +26,9 @@ pub struct TaskData {
     pub lock_name: Vec<String>,
     #[clap(long)]
     pub lock_type: Vec<LockKind>,
+    /// How many tasks may hold the SEMAPHORE locks of this task at once
+    #[clap(long)]
+    pub semaphore_capacity: Option<u32>,
     #[clap(long)]
     pub flake_url: String,
     #[clap(long)]
@@ -34,16 +39,86 @@ pub struct TaskData {
     pub group: Option<String>,
     #[clap(long)]
     pub needs_confirmation: bool,
+    /// Tasks with a higher priority are run first
+    #[clap(long, default_value_t = 0, allow_negative_numbers = true)]
+    pub priority: i32,
+    /// Only run this task after the given task finished successfully
+    #[clap(long)]
+    pub depends_on: Vec<Uuid>,
+    /// Don't run this task before the given RFC 3339 timestamp, e.g. 2026-04-01T02:00:00+02:00
+    #[clap(long)]
+    pub not_before: Option<DateTime<Utc>>,
+    /// Retry the task until it ran this many times
+    #[clap(long)]
+    pub max_attempts: Option<u32>,
+    /// Seconds to wait before the first retry, doubling with every further retry
+    #[clap(long, default_value_t = 0)]
+    pub retry_backoff: u32,
+    /// Results that cause a retry, defaults to ERROR and TIMEOUT
+    #[clap(long)]
+    pub retry_on: Vec<TaskResult>,
+    /// Seconds the task may run before it times out, defaults to the server's limit
+    #[clap(long)]
+    pub max_runtime: Option<u32>,
+    /// Label to classify the task by, given as key=value
+    #[clap(long, value_parser = parse_label)]
+    pub label: Vec<(String, String)>,
+    /// How many users other than the creator have to approve the task before it runs
+    #[clap(long, default_value_t = 0)]
+    pub required_approvals: u32,
+    /// Submitting again with the same key returns the original task instead of a duplicate
+    #[clap(long)]
+    pub idempotency_key: Option<String>,
+    /// Supersede older tasks with the same key that didn't start yet
+    #[clap(long)]
+    pub supersede_key: Option<String>,
+}
+
+fn parse_label(label: &str) -> Result<(String, String), String> {
+    match label.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected key=value, got `{label}`")),
+    }
 }
 
 #[derive(Subcommand, Debug)]
 pub enum TaskCommands {
-    Create(TaskData),
+    Create(Box<TaskData>),
     // TODO: Logs
-    Claim { features: Vec<String> },
-    Finish { id: Uuid, status: TaskResult },
-    Confirm { id: Uuid },
-    Cancel { id: Uuid },
+    Claim {
+        features: Vec<String>,
+    },
+    Finish {
+        id: Uuid,
+        status: TaskResult,
+    },
+    Confirm {
+        id: Uuid,
+    },
+    Cancel {
+        id: Uuid,
+    },
+    /// Refuse a task that waits for confirmation
+    Reject {
+        id: Uuid,
+        /// Why the task is refused, shown to whoever submitted it
+        #[clap(long, short)]
+        comment: String,
+    },
+    /// Create a new task with the same spec as a finished task
+    Rerun {
+        id: Uuid,
+        /// Clear the poison the finished task left on its locks
+        #[clap(long)]
+        clear_poison: bool,
+    },
+    /// Explain why a task is not running yet
+    Why {
+        id: Uuid,
+        /// Features of the fairy that should run the task
+        #[clap(long)]
+        features: Vec<String>,
+    },
 }
 
 #[derive(Args, Debug)]
@@ -56,6 +131,55 @@ pub struct TaskArgs {
     pub ctx: AppContext,
 }
 
+#[derive(Parser, Debug, Clone)]
+pub struct ScheduleData {
+    #[clap(short, long)]
+    pub name: String,
+    /// Cron expression in UTC, e.g. "0 2 * * *" for every night at 02:00
+    #[clap(long)]
+    pub cron: String,
+    #[clap(long)]
+    pub lock_name: Vec<String>,
+    #[clap(long)]
+    pub lock_type: Vec<LockKind>,
+    /// How many tasks may hold the SEMAPHORE locks of this schedule at once
+    #[clap(long)]
+    pub semaphore_capacity: Option<u32>,
+    #[clap(long)]
+    pub flake_url: String,
+    #[clap(long)]
+    pub flake_arg: Vec<String>,
+    #[clap(long)]
+    pub features: Vec<String>,
+    #[clap(short, long)]
+    pub group: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ScheduleCommands {
+    /// Show all schedules
+    List,
+    Create(ScheduleData),
+    Update {
+        id: Uuid,
+        #[command(flatten)]
+        data: ScheduleData,
+    },
+    Delete {
+        id: Uuid,
+    },
+}
+
+#[derive(Args, Debug)]
+#[command(version, about = "Manage recurring tasks on the vicky delegation server", long_about = None)]
+pub struct ScheduleArgs {
+    #[command(subcommand)]
+    pub commands: ScheduleCommands,
+
+    #[command(flatten)]
+    pub ctx: AppContext,
+}
+
 #[derive(Args, Debug)]
 #[command(version, about = "Show all tasks vicky is managing", long_about = None)]
 pub struct TasksArgs {
@@ -64,6 +188,45 @@ pub struct TasksArgs {
     /// By which task group to filter
     #[clap(short, long)]
     pub group: Option<String>,
+    /// By which labels to filter, either key=value or just key to match any value
+    #[clap(short, long)]
+    pub label: Vec<String>,
+    /// By which statuses to filter, e.g. RUNNING or FINISHED::ERROR
+    #[clap(short, long)]
+    pub status: Vec<String>,
+    /// Only show tasks created at or after the given RFC 3339 timestamp
+    #[clap(long)]
+    pub created_after: Option<DateTime<Utc>>,
+    /// Only show tasks created before the given RFC 3339 timestamp
+    #[clap(long)]
+    pub created_before: Option<DateTime<Utc>>,
+    /// Only show tasks finished at or after the given RFC 3339 timestamp
+    #[clap(long)]
+    pub finished_after: Option<DateTime<Utc>>,
+    /// Only show tasks finished before the given RFC 3339 timestamp
+    #[clap(long)]
+    pub finished_before: Option<DateTime<Utc>>,
+    /// Only show tasks holding a lock with this name
+    #[clap(long)]
+    pub lock: Option<String>,
+    /// Only show tasks whose flake URI contains this text
+    #[clap(long)]
+    pub flake: Option<String>,
+    /// Only show tasks whose display name contains this text, ignoring the case
+    #[clap(long)]
+    pub search: Option<String>,
+    /// By which field to sort, defaults to created_at
+    #[clap(long)]
+    pub sort: Option<TaskSort>,
+    /// In which order to sort, defaults to desc
+    #[clap(long)]
+    pub order: Option<SortOrder>,
+    /// How many tasks to fetch at most, or per page with --all
+    #[clap(long)]
+    pub limit: Option<u32>,
+    /// Fetch all pages, printing every task as a JSON line as soon as its page arrives
+    #[clap(long, conflicts_with_all = ["sort", "humanize"])]
+    pub all: bool,
 }
 
 #[derive(Args, Debug)]
@@ -93,6 +256,7 @@ pub struct ResolveArgs {
 pub enum Cli {
     Task(TaskArgs),
     Tasks(TasksArgs),
+    Schedule(ScheduleArgs),
     Locks(LocksArgs),
     Resolve(ResolveArgs),
 }
@@ -3,11 +3,16 @@ mod error;
 mod http_client;
 mod humanize;
 mod locks;
+mod schedules;
 mod tasks;
 mod tui;
 
-use crate::cli::{Cli, TaskCommands};
-use crate::tasks::{cancel_task, claim_task, confirm_task, create_task, finish_task};
+use crate::cli::{Cli, ScheduleCommands, TaskCommands};
+use crate::schedules::{create_schedule, delete_schedule, show_schedules, update_schedule};
+use crate::tasks::{
+    cancel_task, claim_task, confirm_task, create_task, explain_task_readiness, finish_task,
+    reject_task, rerun_task,
+};
 use clap::Parser;
 
 fn main() {
@@ -22,8 +27,23 @@ fn main() {
             TaskCommands::Finish { id, status } => finish_task(&id, status, &task_args.ctx),
             TaskCommands::Confirm { id } => confirm_task(&id, &task_args.ctx),
             TaskCommands::Cancel { id } => cancel_task(&id, &task_args.ctx),
+            TaskCommands::Reject { id, comment } => reject_task(&id, &comment, &task_args.ctx),
+            TaskCommands::Rerun { id, clear_poison } => {
+                rerun_task(&id, clear_poison, &task_args.ctx)
+            }
+            TaskCommands::Why { id, features } => {
+                explain_task_readiness(&id, &features, &task_args.ctx)
+            }
         },
         Cli::Tasks(tasks_args) => tasks::show_tasks(&tasks_args),
+        Cli::Schedule(schedule_args) => match schedule_args.commands {
+            ScheduleCommands::List => show_schedules(&schedule_args.ctx),
+            ScheduleCommands::Create(data) => create_schedule(&data, &schedule_args.ctx),
+            ScheduleCommands::Update { id, data } => {
+                update_schedule(&id, &data, &schedule_args.ctx)
+            }
+            ScheduleCommands::Delete { id } => delete_schedule(&id, &schedule_args.ctx),
+        },
         Cli::Locks(locks_args) => tui::show_locks(&locks_args),
         Cli::Resolve(resolve_args) => tui::resolve_lock(&resolve_args),
     };
@@ -0,0 +1,161 @@
+use crate::cli::{AppContext, ScheduleData};
+use crate::error::Error;
+use crate::http_client::{prepare_client, print_http};
+use crate::humanize;
+use crate::tasks::locks_to_json;
+use reqwest::blocking::Response;
+use serde_json::json;
+use uuid::Uuid;
+use vickylib::database::entities::Schedule;
+use yansi::Paint;
+
+impl ScheduleData {
+    pub fn to_json(&self) -> serde_json::Value {
+        let locks = locks_to_json(&self.lock_name, &self.lock_type, self.semaphore_capacity);
+
+        json!({
+            "display_name": self.name,
+            "cron": self.cron,
+            "flake_ref": {
+                "flake": self.flake_url,
+                "args": self.flake_arg
+            },
+            "locks": locks,
+            "features": self.features,
+            "group": self.group,
+        })
+    }
+}
+
+pub fn show_schedules(ctx: &AppContext) -> Result<(), Error> {
+    if ctx.humanize {
+        humanize::ensure_jless("schedule list")?;
+    }
+
+    let client = prepare_client(ctx)?;
+    let request = client
+        .get(format!("{}/api/v1/schedules", ctx.vicky_url))
+        .build()?;
+    let response = client.execute(request)?.error_for_status()?;
+
+    let text = response.text()?;
+    humanize::handle_user_response(ctx, &text)?;
+    Ok(())
+}
+
+fn print_schedule(response: Response, action: &str, ctx: &AppContext) -> Result<(), Error> {
+    let status = response.status();
+    let text = response.text()?;
+    let schedule: Schedule = serde_json::de::from_str(&text)?;
+    if ctx.humanize {
+        let next_run = schedule.next_run_at.map_or_else(
+            || "never".to_string(),
+            |next_run_at| next_run_at.to_rfc3339(),
+        );
+        print_http(
+            Some(status),
+            &format!(
+                "Schedule was {action} under id {}. Next run: {}",
+                schedule.id.to_string().bright_blue(),
+                next_run.bright_yellow()
+            ),
+        );
+    } else {
+        println!("{}", serde_json::ser::to_string(&schedule)?);
+    }
+    Ok(())
+}
+
+pub fn create_schedule(schedule_data: &ScheduleData, ctx: &AppContext) -> Result<(), Error> {
+    let client = prepare_client(ctx)?;
+    let request = client
+        .post(format!("{}/api/v1/schedules", ctx.vicky_url))
+        .body(schedule_data.to_json().to_string())
+        .build()?;
+
+    let response = client
+        .execute(request)?
+        .error_for_status()
+        .map_err(|e| (e, "Schedule couldn't be created.".to_string()))?;
+
+    print_schedule(response, "created", ctx)
+}
+
+pub fn update_schedule(
+    id: &Uuid,
+    schedule_data: &ScheduleData,
+    ctx: &AppContext,
+) -> Result<(), Error> {
+    let client = prepare_client(ctx)?;
+    let request = client
+        .put(format!("{}/api/v1/schedules/{id}", ctx.vicky_url))
+        .body(schedule_data.to_json().to_string())
+        .build()?;
+
+    let response = client
+        .execute(request)?
+        .error_for_status()
+        .map_err(|e| (e, "Schedule couldn't be updated.".to_string()))?;
+
+    print_schedule(response, "updated", ctx)
+}
+
+pub fn delete_schedule(id: &Uuid, ctx: &AppContext) -> Result<(), Error> {
+    let client = prepare_client(ctx)?;
+    let request = client
+        .delete(format!("{}/api/v1/schedules/{id}", ctx.vicky_url))
+        .build()?;
+
+    let response = client
+        .execute(request)?
+        .error_for_status()
+        .map_err(|e| (e, "Schedule couldn't be deleted.".to_string()))?;
+
+    if ctx.humanize {
+        print_http(Some(response.status()), &format!("Schedule {id} deleted."));
+    } else {
+        println!();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::ScheduleData;
+    use serde_json::json;
+    use vickylib::database::entities::LockKind;
+
+    #[test]
+    fn test_schedule_data_to_json() {
+        let data = ScheduleData {
+            name: "drift check".to_string(),
+            cron: "0 2 * * *".to_string(),
+            lock_name: vec!["config".to_string()],
+            lock_type: vec![LockKind::Read],
+            semaphore_capacity: None,
+            flake_url: "github:wobcom/vicky".to_string(),
+            flake_arg: vec!["drift".to_string()],
+            features: vec![],
+            group: Some("nightly".to_string()),
+        };
+
+        let should_be = json!({
+            "display_name": "drift check",
+            "cron": "0 2 * * *",
+            "locks": [
+                {
+                    "name": "config",
+                    "type": "READ",
+                }
+            ],
+            "flake_ref": {
+                "flake": "github:wobcom/vicky",
+                "args": [ "drift" ]
+            },
+            "features": [],
+            "group": "nightly",
+        });
+
+        assert_eq!(data.to_json(), should_be);
+    }
+}
@@ -3,13 +3,21 @@ use crate::error::Error;
 use crate::http_client::{prepare_client, print_http};
 use crate::humanize;
 use log::debug;
+use reqwest::blocking::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::BTreeMap;
 use uuid::Uuid;
-use vickylib::database::entities::Lock;
-use vickylib::database::entities::task::{FlakeRef, TaskResult, TaskStatus};
+use vickylib::database::entities::task::{
+    FlakeRef, IDEMPOTENCY_KEY_HEADER, TaskResult, TaskStatus,
+};
+use vickylib::database::entities::{Lock, LockKind};
+use vickylib::query::NEXT_CURSOR_HEADER;
+use vickylib::vicky::readiness::TaskReadiness;
 use yansi::Paint;
 
+const DEFAULT_PAGE_SIZE: u32 = 100;
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct Task {
     pub id: Uuid,
@@ -25,15 +33,54 @@ pub fn show_tasks(tasks_args: &TasksArgs) -> Result<(), Error> {
         humanize::ensure_jless("tasks")?;
     }
 
-    let query: &[(_, _)] = match &tasks_args.group {
-        Some(group) => &[("group", group)],
-        None => &[],
-    };
+    let mut query: Vec<(&str, String)> = vec![];
+    if let Some(group) = &tasks_args.group {
+        query.push(("group", group.clone()));
+    }
+    for label in &tasks_args.label {
+        query.push(("label", label.clone()));
+    }
+    for status in &tasks_args.status {
+        query.push(("status", status.clone()));
+    }
+    let time_ranges = [
+        ("created_after", tasks_args.created_after),
+        ("created_before", tasks_args.created_before),
+        ("finished_after", tasks_args.finished_after),
+        ("finished_before", tasks_args.finished_before),
+    ];
+    for (name, time) in time_ranges {
+        if let Some(time) = time {
+            query.push((name, time.timestamp().to_string()));
+        }
+    }
+    if let Some(lock) = &tasks_args.lock {
+        query.push(("lock", lock.clone()));
+    }
+    if let Some(flake) = &tasks_args.flake {
+        query.push(("flake", flake.clone()));
+    }
+    if let Some(search) = &tasks_args.search {
+        query.push(("search", search.clone()));
+    }
+    if let Some(sort) = tasks_args.sort {
+        query.push(("sort", sort.as_str().to_string()));
+    }
+    if let Some(order) = tasks_args.order {
+        query.push(("order", order.as_str().to_string()));
+    }
 
     let client = prepare_client(&tasks_args.ctx)?;
+    if tasks_args.all {
+        return stream_tasks(&client, tasks_args, query);
+    }
+
+    if let Some(limit) = tasks_args.limit {
+        query.push(("limit", limit.to_string()));
+    }
     let request = client
         .get(format!("{}/api/v1/tasks", tasks_args.ctx.vicky_url))
-        .query(query)
+        .query(&query)
         .build()?;
     let response = client.execute(request)?.error_for_status()?;
 
@@ -43,19 +90,81 @@ pub fn show_tasks(tasks_args: &TasksArgs) -> Result<(), Error> {
     Ok(())
 }
 
+/// Follows the cursors of the task list page by page until the last page was printed.
+fn stream_tasks(
+    client: &Client,
+    tasks_args: &TasksArgs,
+    query: Vec<(&str, String)>,
+) -> Result<(), Error> {
+    let page_size = tasks_args.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let mut cursor: Option<String> = None;
+
+    loop {
+        let mut page_query = query.clone();
+        page_query.push(("limit", page_size.to_string()));
+        if let Some(cursor) = cursor.take() {
+            page_query.push(("cursor", cursor));
+        }
+
+        let request = client
+            .get(format!("{}/api/v1/tasks", tasks_args.ctx.vicky_url))
+            .query(&page_query)
+            .build()?;
+        let response = client.execute(request)?.error_for_status()?;
+        cursor = response
+            .headers()
+            .get(NEXT_CURSOR_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        let tasks: Vec<serde_json::Value> = serde_json::from_str(&response.text()?)?;
+        debug!("got a page of {} tasks from server", tasks.len());
+        for task in tasks {
+            println!("{task}");
+        }
+
+        if cursor.is_none() {
+            return Ok(());
+        }
+    }
+}
+
+/// Pairs up lock names and types. SEMAPHORE locks get `semaphore_capacity` as their capacity.
+pub fn locks_to_json(
+    lock_name: &[String],
+    lock_type: &[LockKind],
+    semaphore_capacity: Option<u32>,
+) -> serde_json::Value {
+    lock_name
+        .iter()
+        .zip(lock_type.iter())
+        .map(|(name, ty)| {
+            let mut lock = json!({
+                "name": name,
+                "type": ty
+            });
+            if ty.is_semaphore() {
+                lock["capacity"] = json!(semaphore_capacity);
+            }
+            lock
+        })
+        .collect()
+}
+
 impl TaskData {
     pub fn to_json(&self) -> serde_json::Value {
-        let locks: serde_json::Value = self
-            .lock_name
-            .iter()
-            .zip(self.lock_type.iter())
-            .map(|(name, ty)| {
-                json!({
-                    "name": name,
-                    "type": ty
-                })
-            })
-            .collect();
+        let locks = locks_to_json(&self.lock_name, &self.lock_type, self.semaphore_capacity);
+
+        let retry_policy = self.max_attempts.map(|max_attempts| {
+            let mut policy = json!({
+                "max_attempts": max_attempts,
+                "backoff_sec": self.retry_backoff,
+            });
+            if !self.retry_on.is_empty() {
+                policy["retry_on"] = json!(self.retry_on);
+            }
+            policy
+        });
 
         json!({
             "display_name": self.name,
@@ -67,6 +176,14 @@ impl TaskData {
             "features": self.features,
             "needs_confirmation": self.needs_confirmation,
             "group": self.group,
+            "priority": self.priority,
+            "depends_on": self.depends_on,
+            "not_before": self.not_before.map(|not_before| not_before.timestamp()),
+            "retry_policy": retry_policy,
+            "max_runtime": self.max_runtime,
+            "labels": self.label.iter().cloned().collect::<BTreeMap<_, _>>(),
+            "required_approvals": self.required_approvals,
+            "supersede_key": self.supersede_key,
         })
     }
 }
@@ -84,10 +201,13 @@ struct RoTaskCreate {
 
 pub fn create_task(task_data: &TaskData, ctx: &AppContext) -> Result<(), Error> {
     let client = prepare_client(ctx)?;
-    let request = client
+    let mut request = client
         .post(format!("{}/api/v1/tasks", ctx.vicky_url))
-        .body(task_data.to_json().to_string())
-        .build()?;
+        .body(task_data.to_json().to_string());
+    if let Some(key) = &task_data.idempotency_key {
+        request = request.header(IDEMPOTENCY_KEY_HEADER, key);
+    }
+    let request = request.build()?;
 
     let response = client
         .execute(request)?
@@ -112,6 +232,39 @@ pub fn create_task(task_data: &TaskData, ctx: &AppContext) -> Result<(), Error>
     Ok(())
 }
 
+pub fn rerun_task(id: &Uuid, clear_poison: bool, ctx: &AppContext) -> Result<(), Error> {
+    let client = prepare_client(ctx)?;
+    let data: serde_json::Value = json!({
+        "clear_poison": clear_poison
+    });
+    let request = client
+        .post(format!("{}/api/v1/tasks/{id}/rerun", ctx.vicky_url))
+        .json(&data)
+        .build()?;
+
+    let response = client
+        .execute(request)?
+        .error_for_status()
+        .map_err(|e| (e, "Task couldn't be re-run.".to_string()))?;
+
+    let status = response.status();
+    let text = response.text()?;
+    let pretty_json: RoTaskCreate = serde_json::de::from_str(&text)?;
+    if ctx.humanize {
+        print_http(
+            Some(status),
+            &format!(
+                "Task {id} was re-run under id {}. State: {}",
+                pretty_json.id.bright_blue(),
+                pretty_json.status.state.bright_yellow()
+            ),
+        );
+    } else {
+        println!("{}", serde_json::ser::to_string(&pretty_json)?);
+    }
+    Ok(())
+}
+
 pub fn claim_task(features: &[String], ctx: &AppContext) -> Result<(), Error> {
     let client = prepare_client(ctx)?;
     let data: serde_json::Value = json!({
@@ -201,6 +354,17 @@ pub fn confirm_task(id: &Uuid, ctx: &AppContext) -> Result<(), Error> {
     if ctx.humanize
         && let Ok(task) = serde_json::de::from_str::<Task>(&text)
     {
+        if task.status == TaskStatus::NeedsUserValidation {
+            print_http(
+                Some(status),
+                &format!(
+                    "Approval of task {} recorded. It waits for further approvals.",
+                    task.id.to_string().bright_blue(),
+                ),
+            );
+            return Ok(());
+        }
+
         print_http(
             Some(status),
             &format!(
@@ -239,6 +403,35 @@ pub fn confirm_task(id: &Uuid, ctx: &AppContext) -> Result<(), Error> {
     Ok(())
 }
 
+pub fn reject_task(id: &Uuid, comment: &str, ctx: &AppContext) -> Result<(), Error> {
+    let client = prepare_client(ctx)?;
+    let data: serde_json::Value = json!({
+        "comment": comment
+    });
+    let request = client
+        .post(format!("{}/api/v1/tasks/{id}/reject", ctx.vicky_url))
+        .json(&data)
+        .build()?;
+
+    let response = client
+        .execute(request)?
+        .error_for_status()
+        .map_err(|e| (e, "Task couldn't be rejected".to_string()))?;
+
+    let status = response.status();
+    let text = response.text()?;
+    if ctx.humanize {
+        print_http(
+            Some(status),
+            &format!("Task {} rejected.", id.to_string().bright_blue()),
+        );
+    } else {
+        let pretty_json: serde_json::Value = serde_json::from_str(&text)?;
+        println!("{}", serde_json::ser::to_string(&pretty_json)?);
+    }
+    Ok(())
+}
+
 pub fn cancel_task(id: &Uuid, ctx: &AppContext) -> Result<(), Error> {
     let client = prepare_client(ctx)?;
     let request = client
@@ -265,6 +458,17 @@ pub fn cancel_task(id: &Uuid, ctx: &AppContext) -> Result<(), Error> {
     if ctx.humanize
         && let Ok(task) = serde_json::de::from_str::<Task>(&text)
     {
+        if task.status == TaskStatus::Running {
+            print_http(
+                Some(status),
+                &format!(
+                    "Cancellation of task {} requested. It will be killed on its next heartbeat.",
+                    task.id.to_string().bright_blue(),
+                ),
+            );
+            return Ok(());
+        }
+
         print_http(
             Some(status),
             &format!(
@@ -303,11 +507,54 @@ pub fn cancel_task(id: &Uuid, ctx: &AppContext) -> Result<(), Error> {
     Ok(())
 }
 
+pub fn explain_task_readiness(
+    id: &Uuid,
+    features: &[String],
+    ctx: &AppContext,
+) -> Result<(), Error> {
+    let client = prepare_client(ctx)?;
+    let query: Vec<_> = features
+        .iter()
+        .map(|feature| ("features", feature))
+        .collect();
+    let request = client
+        .get(format!("{}/api/v1/tasks/{id}/readiness", ctx.vicky_url))
+        .query(&query)
+        .build()?;
+
+    let response = client
+        .execute(request)?
+        .error_for_status()
+        .map_err(|e| (e, "Task readiness couldn't be fetched".to_string()))?;
+
+    let status = response.status();
+    let text = response.text()?;
+    let readiness: TaskReadiness = serde_json::de::from_str(&text)?;
+    if ctx.humanize {
+        let explanation = readiness.to_string();
+        let explanation = if readiness.is_ready() {
+            explanation.bright_green()
+        } else {
+            explanation.bright_yellow()
+        };
+        print_http(
+            Some(status),
+            &format!("Task {}: {explanation}", id.to_string().bright_blue()),
+        );
+    } else {
+        println!("{}", serde_json::ser::to_string(&readiness)?);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::cli::TaskData;
+    use chrono::DateTime;
     use serde_json::json;
+    use uuid::Uuid;
     use vickylib::database::entities::LockKind;
+    use vickylib::database::entities::task::TaskResult;
 
     #[test]
     fn test_empty_task_data_to_json() {
@@ -315,11 +562,23 @@ mod tests {
             name: "".to_string(),
             lock_name: vec![],
             lock_type: vec![],
+            semaphore_capacity: None,
             flake_url: "".to_string(),
             flake_arg: vec![],
             features: vec![],
             group: None,
             needs_confirmation: false,
+            priority: 0,
+            depends_on: vec![],
+            not_before: None,
+            max_attempts: None,
+            retry_backoff: 0,
+            retry_on: vec![],
+            max_runtime: None,
+            label: vec![],
+            required_approvals: 0,
+            idempotency_key: None,
+            supersede_key: None,
         };
 
         let should_be = json!({
@@ -332,6 +591,14 @@ mod tests {
             "features": [],
             "needs_confirmation": false,
             "group": null,
+            "priority": 0,
+            "depends_on": [],
+            "not_before": null,
+            "retry_policy": null,
+            "max_runtime": null,
+            "labels": {},
+            "required_approvals": 0,
+            "supersede_key": null,
         });
 
         assert_eq!(data.to_json(), should_be);
@@ -347,6 +614,7 @@ mod tests {
                 "third".to_string(),
             ],
             lock_type: vec![LockKind::Write, LockKind::Write, LockKind::Read],
+            semaphore_capacity: None,
             flake_url: "github:wobcom/vicky".to_string(),
             flake_arg: vec!["flaked".to_string(), "really!".to_string()],
             features: vec![
@@ -357,6 +625,17 @@ mod tests {
             ],
             group: None,
             needs_confirmation: true,
+            priority: 10,
+            depends_on: vec![Uuid::nil()],
+            not_before: DateTime::from_timestamp(1775008800, 0),
+            max_attempts: Some(3),
+            retry_backoff: 30,
+            retry_on: vec![TaskResult::Error],
+            max_runtime: Some(3600),
+            label: vec![("env".to_string(), "prod".to_string())],
+            required_approvals: 2,
+            idempotency_key: Some("ci-1234".to_string()),
+            supersede_key: Some("deploy-core-router".to_string()),
         };
 
         let should_be = json!({
@@ -382,6 +661,76 @@ mod tests {
             "features": [ "feat1", "big_cpu", "huge_cpu", "gigantonormous_gpu" ],
             "needs_confirmation": true,
             "group": null,
+            "priority": 10,
+            "depends_on": [ "00000000-0000-0000-0000-000000000000" ],
+            "not_before": 1775008800,
+            "retry_policy": {
+                "max_attempts": 3,
+                "backoff_sec": 30,
+                "retry_on": [ { "result": "ERROR" } ],
+            },
+            "max_runtime": 3600,
+            "labels": { "env": "prod" },
+            "required_approvals": 2,
+            "supersede_key": "deploy-core-router",
+        });
+
+        assert_eq!(data.to_json(), should_be);
+    }
+
+    #[test]
+    fn test_semaphore_task_data_to_json() {
+        let data = TaskData {
+            name: "reboot router".to_string(),
+            lock_name: vec!["region/wob".to_string(), "config".to_string()],
+            lock_type: vec![LockKind::Semaphore, LockKind::Read],
+            semaphore_capacity: Some(3),
+            flake_url: "github:wobcom/vicky".to_string(),
+            flake_arg: vec![],
+            features: vec![],
+            group: None,
+            needs_confirmation: false,
+            priority: 0,
+            depends_on: vec![],
+            not_before: None,
+            max_attempts: None,
+            retry_backoff: 0,
+            retry_on: vec![],
+            max_runtime: None,
+            label: vec![],
+            required_approvals: 0,
+            idempotency_key: None,
+            supersede_key: None,
+        };
+
+        let should_be = json!({
+            "display_name": "reboot router",
+            "locks": [
+                {
+                    "name": "region/wob",
+                    "type": "SEMAPHORE",
+                    "capacity": 3,
+                },
+                {
+                    "name": "config",
+                    "type": "READ",
+                }
+            ],
+            "flake_ref": {
+                "flake": "github:wobcom/vicky",
+                "args": []
+            },
+            "features": [],
+            "needs_confirmation": false,
+            "group": null,
+            "priority": 0,
+            "depends_on": [],
+            "not_before": null,
+            "retry_policy": null,
+            "max_runtime": null,
+            "labels": {},
+            "required_approvals": 0,
+            "supersede_key": null,
         });
 
         assert_eq!(data.to_json(), should_be);